@@ -1,21 +1,27 @@
-use crate::{MemoryListener, MemorySocket};
+//! Support shared by the `async` and `tokio` features.
+
+use crate::{MemoryListener, MemorySocket, Reader, Writer};
+#[cfg(feature = "async")]
+use crate::{OwnedReadHalf, OwnedWriteHalf, ReadHalf, Recorder, WriteHalf};
 use bytes::{buf::BufExt, Buf};
-use futures::{
-    io::{AsyncRead, AsyncWrite},
-    ready,
-    stream::{FusedStream, Stream},
-};
+use futures_core::{ready, Stream};
 use std::{
+    future::Future,
     io::{ErrorKind, Result},
     pin::Pin,
     task::{Context, Poll},
 };
 
+#[cfg(feature = "async")]
+use futures::io::{AsyncRead, AsyncWrite};
+
 impl MemoryListener {
     /// Returns a stream over the connections being received on this
     /// listener.
     ///
-    /// The returned stream will never return `None`.
+    /// The returned stream ends once the listener is [closed].
+    ///
+    /// [closed]: struct.MemoryListener.html#method.close
     ///
     /// # Examples
     ///
@@ -41,21 +47,63 @@ impl MemoryListener {
         IncomingStream { inner: self }
     }
 
+    /// Asynchronously accept a new incoming connection from this listener.
+    ///
+    /// The returned future resolves once a new connection is established, yielding the
+    /// corresponding [`MemorySocket`].
+    ///
+    /// [`MemorySocket`]: struct.MemorySocket.html
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use memory_socket::MemoryListener;
+    ///
+    /// # async fn work () -> ::std::io::Result<()> {
+    /// let mut listener = MemoryListener::bind("192.51.100.2:60".parse().unwrap()).unwrap();
+    ///
+    /// loop {
+    ///     let socket = listener.accept_async().await?;
+    ///     println!("new client!");
+    /// }
+    /// # Ok(())}
+    /// ```
+    pub fn accept_async(&mut self) -> Accept<'_> {
+        Accept { inner: self }
+    }
+
     fn poll_accept(&mut self, context: &mut Context) -> Poll<Result<MemorySocket>> {
-        match Pin::new(&mut self.incoming).poll_next(context) {
-            Poll::Ready(Some(socket)) => Poll::Ready(Ok(socket)),
-            // The stream will never terminate
-            Poll::Ready(None) => unreachable!(),
-            Poll::Pending => Poll::Pending,
-        }
+        self.backlog.poll_pop(context)
     }
 }
 
-/// A Stream that infinitely accepts connections on a [`MemoryListener`].
+/// A Future which resolves to the next connection accepted on a [`MemoryListener`].
+///
+/// This `struct` is created by the [`accept_async`] method on [`MemoryListener`].
+/// See its documentation for more info.
+///
+/// [`accept_async`]: struct.MemoryListener.html#method.accept_async
+/// [`MemoryListener`]: struct.MemoryListener.html
+pub struct Accept<'a> {
+    inner: &'a mut MemoryListener,
+}
+
+impl<'a> Future for Accept<'a> {
+    type Output = Result<MemorySocket>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        self.inner.poll_accept(context)
+    }
+}
+
+/// A Stream that accepts connections on a [`MemoryListener`] until it is closed.
 ///
 /// This `struct` is created by the [`incoming_stream`] method on [`MemoryListener`].
 /// See its documentation for more info.
 ///
+/// The `Stream` trait implemented here is the one shared by the `futures` and `tokio-stream`
+/// crates, so it can be used with the combinators of either.
+///
 /// [`incoming_stream`]: struct.MemoryListener.html#method.incoming_stream
 /// [`MemoryListener`]: struct.MemoryListener.html
 pub struct IncomingStream<'a> {
@@ -66,26 +114,25 @@ impl<'a> Stream for IncomingStream<'a> {
     type Item = Result<MemorySocket>;
 
     fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
-        let socket = ready!(self.inner.poll_accept(context)?);
-        Poll::Ready(Some(Ok(socket)))
+        match ready!(self.inner.poll_accept(context)) {
+            Err(_) if self.inner.backlog.is_closed() => Poll::Ready(None),
+            result => Poll::Ready(Some(result)),
+        }
     }
 }
 
-impl AsyncRead for MemorySocket {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
-        mut context: &mut Context,
+impl Reader {
+    pub(crate) fn poll_read(
+        &mut self,
+        context: &mut Context,
         buf: &mut [u8],
     ) -> Poll<Result<usize>> {
-        if self.incoming.is_terminated() {
-            if self.seen_eof {
-                return Poll::Ready(Err(ErrorKind::UnexpectedEof.into()));
-            } else {
-                self.seen_eof = true;
-                return Poll::Ready(Ok(0));
-            }
-        }
+        let bytes_read = ready!(self.poll_read_buffered(context, buf))?;
+        self.counters.record_read(bytes_read);
+        Poll::Ready(Ok(bytes_read))
+    }
 
+    fn poll_read_buffered(&mut self, context: &mut Context, buf: &mut [u8]) -> Poll<Result<usize>> {
         let mut bytes_read = 0;
 
         loop {
@@ -114,11 +161,18 @@ impl AsyncRead for MemorySocket {
                         return Poll::Ready(Ok(bytes_read));
                     }
 
-                    self.current_buffer = {
-                        match Pin::new(&mut self.incoming).poll_next(&mut context) {
-                            Poll::Pending => return Poll::Pending,
-                            Poll::Ready(Some(buf)) => Some(buf),
-                            Poll::Ready(None) => return Poll::Ready(Ok(bytes_read)),
+                    self.current_buffer = match ready!(self.incoming.poll_recv(context))? {
+                        Some(buf) => Some(buf),
+
+                        // The remote side hung up, if this is the first time we've seen EOF then
+                        // we should return `Ok(0)` otherwise an UnexpectedEof Error
+                        None => {
+                            if self.seen_eof {
+                                return Poll::Ready(Err(ErrorKind::UnexpectedEof.into()));
+                            } else {
+                                self.seen_eof = true;
+                                return Poll::Ready(Ok(0));
+                            }
                         }
                     };
                 }
@@ -127,34 +181,119 @@ impl AsyncRead for MemorySocket {
     }
 }
 
-impl AsyncWrite for MemorySocket {
-    fn poll_write(
-        mut self: Pin<&mut Self>,
-        _context: &mut Context,
-        buf: &[u8],
-    ) -> Poll<Result<usize>> {
-        self.write_buffer.extend_from_slice(buf);
+impl Writer {
+    pub(crate) fn poll_write(&mut self, context: &mut Context, buf: &[u8]) -> Poll<Result<usize>> {
+        self.outgoing.check()?;
+
+        // Avoid growing the write buffer without bound when the peer isn't keeping up
+        if let Some(capacity) = self.outgoing.capacity() {
+            if self.buffered() >= capacity {
+                ready!(self.poll_flush(context))?;
+            }
+        }
+
+        self.buffer(buf)?;
+        self.counters.record_write(buf.len());
+        if self.should_flush() {
+            // The data has already been accepted, so if it can't be sent right away it stays
+            // buffered until the next flush
+            if let Poll::Ready(Err(error)) = self.poll_flush(context) {
+                return Poll::Ready(Err(error));
+            }
+        }
         Poll::Ready(Ok(buf.len()))
     }
 
-    fn poll_flush(mut self: Pin<&mut Self>, _context: &mut Context) -> Poll<Result<()>> {
-        use flume::TrySendError;
-
+    pub(crate) fn poll_flush(&mut self, context: &mut Context) -> Poll<Result<()>> {
         if !self.write_buffer.is_empty() {
-            let buffer = self.write_buffer.split().freeze();
-            match self.outgoing.try_send(buffer) {
-                Ok(()) => Poll::Ready(Ok(())),
-                Err(TrySendError::Disconnected(_)) => {
-                    Poll::Ready(Err(ErrorKind::BrokenPipe.into()))
-                }
-                Err(TrySendError::Full(_)) => unreachable!(),
-            }
-        } else {
-            Poll::Ready(Ok(()))
+            ready!(self.outgoing.poll_send(context, &mut self.write_buffer))?;
         }
+        self.outgoing.poll_send_staged(context)
     }
 
-    fn poll_close(self: Pin<&mut Self>, _context: &mut Context) -> Poll<Result<()>> {
+    /// Flush any buffered data and close the write side of the connection.
+    pub(crate) fn poll_close(&mut self, context: &mut Context) -> Poll<Result<()>> {
+        if !self.outgoing.is_closed() {
+            ready!(self.poll_flush(context))?;
+        }
+        self.outgoing.close();
         Poll::Ready(Ok(()))
     }
 }
+
+/// Implement `AsyncRead` for types which hold the reading half of a socket in a `reader` field.
+#[cfg(feature = "async")]
+macro_rules! impl_async_read {
+    ($($ty:ty),*) => {$(
+        impl AsyncRead for $ty {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                context: &mut Context,
+                buf: &mut [u8],
+            ) -> Poll<Result<usize>> {
+                self.get_mut().reader.poll_read(context, buf)
+            }
+        }
+    )*};
+}
+
+/// Implement `AsyncWrite` for types which hold the writing half of a socket in a `writer` field.
+#[cfg(feature = "async")]
+macro_rules! impl_async_write {
+    ($($ty:ty),*) => {$(
+        impl AsyncWrite for $ty {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                context: &mut Context,
+                buf: &[u8],
+            ) -> Poll<Result<usize>> {
+                self.get_mut().writer.poll_write(context, buf)
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<()>> {
+                self.get_mut().writer.poll_flush(context)
+            }
+
+            fn poll_close(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<()>> {
+                self.get_mut().writer.poll_close(context)
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "async")]
+impl_async_read!(MemorySocket, ReadHalf<'_>, OwnedReadHalf);
+#[cfg(feature = "async")]
+impl_async_write!(MemorySocket, WriteHalf<'_>, OwnedWriteHalf);
+
+#[cfg(feature = "async")]
+impl<S: AsyncRead + Unpin> AsyncRead for Recorder<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize>> {
+        let recorder = self.get_mut();
+        let len = ready!(Pin::new(recorder.get_mut()).poll_read(context, buf))?;
+        recorder.record_read(buf.len(), &buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<S: AsyncWrite + Unpin> AsyncWrite for Recorder<S> {
+    fn poll_write(self: Pin<&mut Self>, context: &mut Context, buf: &[u8]) -> Poll<Result<usize>> {
+        let recorder = self.get_mut();
+        let len = ready!(Pin::new(recorder.get_mut()).poll_write(context, buf))?;
+        recorder.record_write(&buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<()>> {
+        Pin::new(self.get_mut().get_mut()).poll_flush(context)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<()>> {
+        Pin::new(self.get_mut().get_mut()).poll_close(context)
+    }
+}
@@ -0,0 +1,189 @@
+//! The queue of connections waiting to be accepted by a [`MemoryListener`].
+//!
+//! [`MemoryListener`]: ../struct.MemoryListener.html
+
+use crate::{
+    stats::{Counters, ListenerCounters},
+    ListenerStats, MemorySocket,
+};
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind, Result},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    task::Waker,
+    time::Instant,
+};
+
+#[cfg(any(feature = "async", feature = "tokio"))]
+use std::task::{Context, Poll};
+
+pub(crate) struct Backlog {
+    state: Mutex<State>,
+    /// Signalled when a connection is queued or the backlog is closed
+    not_empty: Condvar,
+    /// Signalled when a connection is accepted or the backlog is closed
+    not_full: Condvar,
+    /// Traffic counters shared by every accepted connection
+    counters: Arc<ListenerCounters>,
+}
+
+struct State {
+    queue: VecDeque<MemorySocket>,
+    /// The maximum number of connections waiting to be accepted, if bounded
+    capacity: Option<usize>,
+    closed: bool,
+    accept_waker: Option<Waker>,
+}
+
+impl State {
+    fn is_full(&self) -> bool {
+        match self.capacity {
+            Some(capacity) => self.queue.len() >= capacity,
+            None => false,
+        }
+    }
+}
+
+impl Backlog {
+    pub(crate) fn new(capacity: Option<usize>) -> Self {
+        Self {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                capacity,
+                closed: false,
+                accept_waker: None,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            counters: Arc::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Queue a new connection to be accepted, calling `queued` just before it can be accepted.
+    ///
+    /// If the backlog is full the connection is refused, unless a `deadline` is given in which
+    /// case this blocks until there is room, returning an error of kind `TimedOut` if the deadline
+    /// passes first.
+    pub(crate) fn push<F: FnOnce()>(
+        &self,
+        socket: MemorySocket,
+        deadline: Option<Instant>,
+        queued: F,
+    ) -> Result<()> {
+        let mut state = self.lock();
+        loop {
+            if state.closed {
+                return Err(ErrorKind::ConnectionRefused.into());
+            }
+            if !state.is_full() {
+                break;
+            }
+
+            let deadline = deadline.ok_or(ErrorKind::ConnectionRefused)?;
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(ErrorKind::TimedOut.into());
+            }
+            state = self.not_full.wait_timeout(state, deadline - now).unwrap().0;
+        }
+
+        queued();
+        state.queue.push_back(socket);
+        self.not_empty.notify_one();
+        if let Some(waker) = state.accept_waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Block until a connection is queued, returning an error of kind `WouldBlock` if `deadline`
+    /// passes first or of kind `ConnectionAborted` if the backlog has been closed.
+    pub(crate) fn pop(&self, deadline: Option<Instant>) -> Result<MemorySocket> {
+        let mut state = self.lock();
+        loop {
+            if let Some(socket) = self.take(&mut state)? {
+                return Ok(socket);
+            }
+
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(ErrorKind::WouldBlock.into());
+                    }
+                    self.not_empty
+                        .wait_timeout(state, deadline - now)
+                        .unwrap()
+                        .0
+                }
+                None => self.not_empty.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Poll for a queued connection, registering the current task to be woken once one arrives.
+    #[cfg(any(feature = "async", feature = "tokio"))]
+    pub(crate) fn poll_pop(&self, context: &mut Context) -> Poll<Result<MemorySocket>> {
+        let mut state = self.lock();
+        match self.take(&mut state)? {
+            Some(socket) => Poll::Ready(Ok(socket)),
+            None => {
+                state.accept_waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn take(&self, state: &mut State) -> Result<Option<MemorySocket>> {
+        if state.closed {
+            return Err(ErrorKind::ConnectionAborted.into());
+        }
+
+        let mut socket = state.queue.pop_front();
+        if let Some(socket) = &mut socket {
+            self.not_full.notify_one();
+            socket.set_counters(Counters::accepted(self.counters.clone()));
+        }
+        Ok(socket)
+    }
+
+    pub(crate) fn stats(&self) -> ListenerStats {
+        self.counters.snapshot()
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    /// Stop accepting connections, resetting any which are queued and waking up anyone waiting on
+    /// the backlog.
+    pub(crate) fn close(&self) {
+        // Reset the queued connections without holding the lock
+        for socket in self.take_queue() {
+            socket.abort();
+        }
+    }
+
+    /// Stop accepting connections, closing any which are queued cleanly so that their peers
+    /// observe EOF when reading and a broken pipe when writing.
+    pub(crate) fn shut_down(&self) {
+        drop(self.take_queue());
+    }
+
+    /// Mark the backlog as closed, waking up anyone waiting on it, and take the queued
+    /// connections.
+    fn take_queue(&self) -> VecDeque<MemorySocket> {
+        let mut state = self.lock();
+        state.closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+        if let Some(waker) = state.accept_waker.take() {
+            waker.wake();
+        }
+        std::mem::take(&mut state.queue)
+    }
+}
@@ -0,0 +1,282 @@
+use crate::{
+    flush::{DiscardPolicy, FlushPolicy},
+    link::{Link, LinkConfig},
+    network, pipe,
+    rng::Rng,
+    segment::{SegmentConfig, Segmenter},
+    MemoryListener, MemoryNetwork, MemorySocket,
+};
+use std::{io::Result, net::SocketAddr};
+
+/// A builder for configuring both sides of an in-memory socket.
+///
+/// This `struct` is created by the [`builder`] method on [`MemorySocket`].
+///
+/// [`builder`]: struct.MemorySocket.html#method.builder
+/// [`MemorySocket`]: struct.MemorySocket.html
+///
+/// # Examples
+///
+/// ```
+/// use memory_socket::MemorySocket;
+///
+/// let (socket_a, socket_b) = MemorySocket::builder().capacity(1024).pair();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PairBuilder {
+    capacity: Option<usize>,
+    link: Option<LinkConfig>,
+    segmentation: Option<SegmentConfig>,
+    flush_policy: Option<FlushPolicy>,
+    discard_policy: Option<DiscardPolicy>,
+    seed: Option<u64>,
+}
+
+impl PairBuilder {
+    /// Create a new builder with the default configuration.
+    ///
+    /// By default the number of bytes in flight in each direction is unbounded and data is
+    /// delivered immediately.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bound the number of bytes which can be in flight in each direction.
+    ///
+    /// Once `capacity` bytes have been flushed without being read by the peer, further flushes
+    /// will block (or return `Poll::Pending` when used asynchronously) until the peer catches up.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is `0`.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be greater than zero");
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Condition the data flowing in each direction with `link`, delaying its delivery.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_socket::{LinkConfig, MemorySocket};
+    ///
+    /// let link = LinkConfig::new().latency(Duration::from_millis(50));
+    /// let (socket_a, socket_b) = MemorySocket::builder().link(link).pair();
+    /// ```
+    pub fn link(mut self, link: LinkConfig) -> Self {
+        self.link = Some(link);
+        self
+    }
+
+    /// Break up the data flushed to each side of the pair, and optionally merge it back together,
+    /// before the peer reads it.
+    ///
+    /// See [`SegmentConfig`] for more details.
+    ///
+    /// [`SegmentConfig`]: struct.SegmentConfig.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{MemorySocket, SegmentConfig};
+    ///
+    /// let segmentation = SegmentConfig::new().mtu(1500);
+    /// let (socket_a, socket_b) = MemorySocket::builder().segmentation(segmentation).pair();
+    /// ```
+    pub fn segmentation(mut self, segmentation: SegmentConfig) -> Self {
+        self.segmentation = Some(segmentation);
+        self
+    }
+
+    /// Set when data written to either side of the pair is sent without an explicit `flush`.
+    ///
+    /// See [`FlushPolicy`] for more details.
+    ///
+    /// [`FlushPolicy`]: enum.FlushPolicy.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{FlushPolicy, MemorySocket};
+    ///
+    /// let (socket_a, socket_b) = MemorySocket::builder()
+    ///     .flush_policy(FlushPolicy::Manual)
+    ///     .pair();
+    /// ```
+    pub fn flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush_policy = Some(policy);
+        self
+    }
+
+    /// Set what happens when either side of the pair is dropped with written data which can no
+    /// longer be delivered.
+    ///
+    /// See [`DiscardPolicy`] for more details.
+    ///
+    /// [`DiscardPolicy`]: enum.DiscardPolicy.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{DiscardPolicy, MemorySocket};
+    ///
+    /// let (socket_a, socket_b) = MemorySocket::builder()
+    ///     .discard_policy(DiscardPolicy::Panic)
+    ///     .pair();
+    /// ```
+    pub fn discard_policy(mut self, policy: DiscardPolicy) -> Self {
+        self.discard_policy = Some(policy);
+        self
+    }
+
+    /// Seed the randomness used by the connection, such as the jitter of its [link] and its
+    /// [segmentation], so that its behavior can be reproduced exactly.
+    ///
+    /// Without a seed the connection's seed is derived from the process seed, which is read from
+    /// the `MEMORY_SOCKET_SEED` environment variable if it is set or chosen at random otherwise,
+    /// and printed if either socket is dropped while the thread is panicking.
+    ///
+    /// [link]: #method.link
+    /// [segmentation]: #method.segmentation
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_socket::{LinkConfig, MemorySocket};
+    ///
+    /// let link = LinkConfig::new().jitter(Duration::from_millis(10));
+    /// let (socket_a, socket_b) = MemorySocket::builder().link(link).seed(42).pair();
+    /// ```
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Construct both sides of an in-memory socket using this configuration.
+    pub fn pair(&self) -> (MemorySocket, MemorySocket) {
+        let mut rng = Rng::new(match self.seed {
+            Some(seed) => seed,
+            None => network::next_process_seed(),
+        });
+        let (a_tx, a_rx) = pipe::pipe(
+            self.capacity,
+            self.new_link(&mut rng),
+            self.new_segmenter(&mut rng),
+        );
+        let (b_tx, b_rx) = pipe::pipe(
+            self.capacity,
+            self.new_link(&mut rng),
+            self.new_segmenter(&mut rng),
+        );
+        let mut a = MemorySocket::new(a_rx, b_tx);
+        let mut b = MemorySocket::new(b_rx, a_tx);
+        if let Some(policy) = self.flush_policy {
+            a.set_flush_policy(policy);
+            b.set_flush_policy(policy);
+        }
+        if let Some(policy) = self.discard_policy {
+            a.set_discard_policy(policy);
+            b.set_discard_policy(policy);
+        }
+        if self.seed.is_none() {
+            a.set_process_seeded();
+            b.set_process_seeded();
+        }
+
+        (a, b)
+    }
+
+    /// Create the link for one direction of the connection, seeded from `rng`.
+    fn new_link(&self, rng: &mut Rng) -> Option<Link> {
+        self.link
+            .clone()
+            .map(|config| Link::new(config, Rng::new(rng.next_u64())))
+    }
+
+    /// Create the segmenter for one direction of the connection, seeded from `rng`.
+    fn new_segmenter(&self, rng: &mut Rng) -> Option<Segmenter> {
+        self.segmentation
+            .clone()
+            .map(|config| Segmenter::new(config, Rng::new(rng.next_u64())))
+    }
+}
+
+/// A builder for configuring a [`MemoryListener`] before binding it.
+///
+/// This `struct` is created by the [`builder`] method on [`MemoryListener`].
+///
+/// [`builder`]: struct.MemoryListener.html#method.builder
+/// [`MemoryListener`]: struct.MemoryListener.html
+///
+/// # Examples
+///
+/// ```
+/// use memory_socket::MemoryListener;
+///
+/// # fn main () -> ::std::io::Result<()> {
+/// let listener = MemoryListener::builder()
+///     .backlog(16)
+///     .bind("192.51.100.2:1338".parse().unwrap())?;
+/// # Ok(())}
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ListenerBuilder {
+    backlog: Option<usize>,
+}
+
+impl ListenerBuilder {
+    /// Create a new builder with the default configuration.
+    ///
+    /// By default the number of connections waiting to be accepted is unbounded.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the number of connections waiting to be accepted to `backlog`.
+    ///
+    /// Once the backlog is full, further attempts to connect to the listener fail with an error
+    /// of kind `ConnectionRefused`, unless made with [`MemorySocket::connect_timeout`] in which
+    /// case they wait for room in the backlog.
+    ///
+    /// [`MemorySocket::connect_timeout`]: struct.MemorySocket.html#method.connect_timeout
+    ///
+    /// # Panics
+    ///
+    /// Panics if `backlog` is `0`.
+    pub fn backlog(mut self, backlog: usize) -> Self {
+        assert!(backlog > 0, "backlog must be greater than zero");
+        self.backlog = Some(backlog);
+        self
+    }
+
+    /// Bind a listener using this configuration to the specified address in the default network.
+    ///
+    /// See [`MemoryListener::bind`] for more details.
+    ///
+    /// [`MemoryListener::bind`]: struct.MemoryListener.html#method.bind
+    pub fn bind(&self, address: SocketAddr) -> Result<MemoryListener> {
+        self.bind_in(MemoryNetwork::default_network(), address)
+    }
+
+    /// Bind a listener using this configuration to the specified address in `network`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{MemoryListener, MemoryNetwork};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// let listener = MemoryListener::builder()
+    ///     .backlog(1)
+    ///     .bind_in(&network, "192.51.100.2:80".parse().unwrap())?;
+    /// # Ok(())}
+    /// ```
+    pub fn bind_in(&self, network: &MemoryNetwork, address: SocketAddr) -> Result<MemoryListener> {
+        network.bind_listener(address, self.backlog)
+    }
+}
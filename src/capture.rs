@@ -0,0 +1,348 @@
+//! Recording the traffic of a [`MemoryNetwork`] in the pcapng format.
+//!
+//! Every chunk of bytes flushed onto a connection's [pipe] is written out as a packet with
+//! synthesized IP and TCP headers, so that tools such as Wireshark can reassemble the streams and
+//! decode the payload with their standard dissectors. Connections are opened with a SYN
+//! handshake once the listener has queued them, or refused with a RST, and end with a FIN or RST,
+//! mirroring how they were torn down.
+//!
+//! [`MemoryNetwork`]: ../struct.MemoryNetwork.html
+//! [pipe]: ../pipe/index.html
+
+use std::{
+    io::{Result, Write},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Link type for packets which begin with a raw IPv4 or IPv6 header.
+const LINKTYPE_RAW: u16 = 101;
+
+/// The largest TCP payload placed in a single packet, leaving room for the headers within the
+/// 16-bit length fields of IPv4 and IPv6.
+const MAX_SEGMENT: usize = 65000;
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+
+/// A pcapng file which packets are written to.
+pub(crate) struct Capture {
+    /// The destination of the capture, or `None` once the capture has been stopped
+    writer: Mutex<Option<Sink>>,
+}
+
+struct Sink {
+    writer: Box<dyn Write + Send>,
+    /// The first error encountered while writing, reported when the capture is stopped
+    error: Option<std::io::Error>,
+}
+
+impl Capture {
+    /// Start a capture, writing the pcapng section header and interface description to `writer`.
+    pub(crate) fn new(mut writer: Box<dyn Write + Send>) -> Result<Self> {
+        // Section Header Block
+        let mut block = Vec::with_capacity(16);
+        block.extend_from_slice(&0x1A2B_3C4Du32.to_le_bytes());
+        block.extend_from_slice(&1u16.to_le_bytes());
+        block.extend_from_slice(&0u16.to_le_bytes());
+        block.extend_from_slice(&(-1i64).to_le_bytes());
+        write_block(&mut writer, 0x0A0D_0D0A, &block)?;
+
+        // Interface Description Block, with no limit on the length of captured packets
+        let mut block = Vec::with_capacity(8);
+        block.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        block.extend_from_slice(&0u16.to_le_bytes());
+        block.extend_from_slice(&0u32.to_le_bytes());
+        write_block(&mut writer, 1, &block)?;
+
+        Ok(Self {
+            writer: Mutex::new(Some(Sink {
+                writer,
+                error: None,
+            })),
+        })
+    }
+
+    /// Record `packet` with the current time.
+    fn record(&self, packet: &[u8]) {
+        let mut writer = self.writer.lock().unwrap();
+        let sink = match writer.as_mut() {
+            Some(sink) if sink.error.is_none() => sink,
+            _ => return,
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_micros() as u64)
+            .unwrap_or(0);
+
+        // Enhanced Packet Block, on the first interface with microsecond timestamps
+        let mut block = Vec::with_capacity(20 + packet.len() + 3);
+        block.extend_from_slice(&0u32.to_le_bytes());
+        block.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+        block.extend_from_slice(&(timestamp as u32).to_le_bytes());
+        block.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        block.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        block.extend_from_slice(packet);
+        block.resize(block.len() + padding(packet.len()), 0);
+
+        if let Err(error) = write_block(&mut sink.writer, 6, &block) {
+            sink.error = Some(error);
+        }
+    }
+
+    /// Stop the capture, flushing and closing the writer. Any error encountered while writing
+    /// the capture is returned.
+    pub(crate) fn finish(&self) -> Result<()> {
+        match self.writer.lock().unwrap().take() {
+            Some(Sink {
+                error: Some(error), ..
+            }) => Err(error),
+            Some(mut sink) => sink.writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Write a pcapng block of type `kind` with the given body, which must be padded to 32 bits.
+fn write_block(writer: &mut dyn Write, kind: u32, body: &[u8]) -> Result<()> {
+    let length = (body.len() as u32 + 12).to_le_bytes();
+    writer.write_all(&kind.to_le_bytes())?;
+    writer.write_all(&length)?;
+    writer.write_all(body)?;
+    writer.write_all(&length)
+}
+
+/// The number of bytes needed to pad `len` to a multiple of 32 bits.
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+/// The synthesized TCP state of a single captured connection.
+pub(crate) struct Flow {
+    capture: Arc<Capture>,
+    /// The client's and the server's address, in that order
+    endpoints: [SocketAddr; 2],
+    state: Mutex<FlowState>,
+}
+
+struct FlowState {
+    /// The next sequence number to be sent by the client and the server
+    seq: [u32; 2],
+    /// Whether the server has answered the client's SYN
+    established: bool,
+    /// Whether each side has sent a FIN
+    fin: [bool; 2],
+    reset: bool,
+}
+
+/// Which side of a connection sent a packet.
+#[derive(Clone, Copy)]
+pub(crate) enum Direction {
+    ToServer = 0,
+    ToClient = 1,
+}
+
+impl Flow {
+    /// Begin capturing a connection from `client` to `server`, recording the client's SYN.
+    pub(crate) fn open(capture: Arc<Capture>, client: SocketAddr, server: SocketAddr) -> Arc<Self> {
+        let flow = Flow {
+            capture,
+            endpoints: [client, server],
+            state: Mutex::new(FlowState {
+                seq: [0, 0],
+                established: false,
+                fin: [false, false],
+                reset: false,
+            }),
+        };
+
+        {
+            let mut state = flow.state.lock().unwrap();
+            flow.segment(&mut state, Direction::ToServer, SYN, &[]);
+        }
+
+        Arc::new(flow)
+    }
+
+    /// Record the rest of the handshake, once the connection has been queued by the listener.
+    pub(crate) fn accept(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.established && !state.reset {
+            state.established = true;
+            self.segment(&mut state, Direction::ToClient, SYN | ACK, &[]);
+            self.segment(&mut state, Direction::ToServer, ACK, &[]);
+        }
+    }
+
+    /// Record the server refusing the connection with a RST.
+    pub(crate) fn refuse(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.established && !state.reset {
+            state.reset = true;
+            self.segment(&mut state, Direction::ToClient, RST | ACK, &[]);
+        }
+    }
+
+    /// Record a single packet sent in `direction`, advancing its sequence number.
+    fn segment(&self, state: &mut FlowState, direction: Direction, flags: u8, payload: &[u8]) {
+        let from = direction as usize;
+        let to = 1 - from;
+        // Nothing has been received from the server yet when the client sends its SYN
+        let ack = if flags & ACK != 0 { state.seq[to] } else { 0 };
+        let packet = packet(
+            self.endpoints[from],
+            self.endpoints[to],
+            state.seq[from],
+            ack,
+            flags,
+            payload,
+        );
+
+        let consumed = payload.len() as u32 + u32::from(flags & (SYN | FIN) != 0);
+        state.seq[from] = state.seq[from].wrapping_add(consumed);
+        self.capture.record(&packet);
+    }
+}
+
+/// Records the packets sent in one direction of a connection.
+pub(crate) struct Tap {
+    flow: Arc<Flow>,
+    direction: Direction,
+}
+
+impl Tap {
+    pub(crate) fn new(flow: Arc<Flow>, direction: Direction) -> Self {
+        Self { flow, direction }
+    }
+
+    /// Record data sent in this direction, unless the connection was never established or has
+    /// already been reset.
+    pub(crate) fn data(&self, bytes: &[u8]) {
+        let mut state = self.flow.state.lock().unwrap();
+        if !state.established || state.reset {
+            return;
+        }
+        for payload in bytes.chunks(MAX_SEGMENT) {
+            self.flow
+                .segment(&mut state, self.direction, PSH | ACK, payload);
+        }
+    }
+
+    /// Record this direction being closed, unless the connection was never established or has
+    /// already been reset.
+    pub(crate) fn fin(&self) {
+        let mut state = self.flow.state.lock().unwrap();
+        let from = self.direction as usize;
+        if state.established && !state.reset && !state.fin[from] {
+            state.fin[from] = true;
+            self.flow
+                .segment(&mut state, self.direction, FIN | ACK, &[]);
+        }
+    }
+
+    /// Record the connection being reset. Only the first reset of a connection is recorded.
+    pub(crate) fn reset(&self) {
+        let mut state = self.flow.state.lock().unwrap();
+        if !state.reset {
+            state.reset = true;
+            self.flow
+                .segment(&mut state, self.direction, RST | ACK, &[]);
+        }
+    }
+}
+
+/// Build an IP packet holding a TCP segment.
+fn packet(
+    source: SocketAddr,
+    destination: SocketAddr,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut tcp = Vec::with_capacity(20 + payload.len());
+    tcp.extend_from_slice(&source.port().to_be_bytes());
+    tcp.extend_from_slice(&destination.port().to_be_bytes());
+    tcp.extend_from_slice(&seq.to_be_bytes());
+    tcp.extend_from_slice(&ack.to_be_bytes());
+    tcp.push(5 << 4);
+    tcp.push(flags);
+    tcp.extend_from_slice(&u16::MAX.to_be_bytes());
+    tcp.extend_from_slice(&[0, 0, 0, 0]);
+    tcp.extend_from_slice(payload);
+
+    let mut packet = Vec::with_capacity(40 + tcp.len());
+    let mut pseudo_header = Vec::with_capacity(36);
+    match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            let mut header = Vec::with_capacity(20);
+            header.extend_from_slice(&[0x45, 0]);
+            header.extend_from_slice(&(20 + tcp.len() as u16).to_be_bytes());
+            // No identification, don't fragment
+            header.extend_from_slice(&[0, 0, 0x40, 0]);
+            header.extend_from_slice(&[64, 6, 0, 0]);
+            header.extend_from_slice(&source.octets());
+            header.extend_from_slice(&destination.octets());
+            let checksum = checksum(&[&header]);
+            header[10..12].copy_from_slice(&checksum.to_be_bytes());
+            packet.extend_from_slice(&header);
+
+            pseudo_header.extend_from_slice(&source.octets());
+            pseudo_header.extend_from_slice(&destination.octets());
+            pseudo_header.extend_from_slice(&[0, 6]);
+            pseudo_header.extend_from_slice(&(tcp.len() as u16).to_be_bytes());
+        }
+        // A connection between the two families is recorded as IPv6, using IPv4-mapped addresses
+        (source, destination) => {
+            let source = to_ipv6(source).octets();
+            let destination = to_ipv6(destination).octets();
+            packet.extend_from_slice(&[0x60, 0, 0, 0]);
+            packet.extend_from_slice(&(tcp.len() as u16).to_be_bytes());
+            packet.extend_from_slice(&[6, 64]);
+            packet.extend_from_slice(&source);
+            packet.extend_from_slice(&destination);
+
+            pseudo_header.extend_from_slice(&source);
+            pseudo_header.extend_from_slice(&destination);
+            pseudo_header.extend_from_slice(&(tcp.len() as u32).to_be_bytes());
+            pseudo_header.extend_from_slice(&[0, 0, 0, 6]);
+        }
+    }
+
+    let checksum = checksum(&[&pseudo_header, &tcp]);
+    tcp[16..18].copy_from_slice(&checksum.to_be_bytes());
+    packet.extend_from_slice(&tcp);
+    packet
+}
+
+fn to_ipv6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+/// The internet checksum of the concatenation of `parts`, each of which has an even length
+/// except possibly the last.
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    for part in parts {
+        for pair in part.chunks(2) {
+            let word = match *pair {
+                [high, low] => u16::from_be_bytes([high, low]),
+                [high] => u16::from_be_bytes([high, 0]),
+                _ => unreachable!(),
+            };
+            sum += u32::from(word);
+        }
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
@@ -0,0 +1,400 @@
+//! Connectionless, message oriented sockets.
+
+use crate::{check_timeout, network::Network, MemoryNetwork};
+use bytes::Bytes;
+use flume::Receiver;
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Result},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+/// A single message in flight, along with the addresses of its sender and its destination.
+pub(crate) type Datagram = (SocketAddr, SocketAddr, Bytes);
+
+/// An in-memory datagram socket, analogous to the standard library `UdpSocket`.
+///
+/// After creating a `MemoryDatagramSocket` by [`bind`]ing it to a socket address, data can be
+/// [sent to] and [received from] any other address. Message boundaries are preserved: each call
+/// to [`recv_from`] returns exactly one message.
+///
+/// Datagram sockets live in a separate table from [`MemoryListener`]s, so a listener and a
+/// datagram socket can be bound to the same address. As with UDP, messages sent to an address
+/// which nothing is bound to are silently dropped.
+///
+/// The socket will be closed when the value is dropped.
+///
+/// [`bind`]: #method.bind
+/// [received from]: #method.recv_from
+/// [`recv_from`]: #method.recv_from
+/// [sent to]: #method.send_to
+/// [`MemoryListener`]: struct.MemoryListener.html
+///
+/// # Examples
+///
+/// ```
+/// use memory_socket::MemoryDatagramSocket;
+///
+/// # fn main () -> ::std::io::Result<()> {
+/// let socket = MemoryDatagramSocket::bind("192.51.100.2:53".parse().unwrap())?;
+/// let client = MemoryDatagramSocket::bind("192.51.100.3:0".parse().unwrap())?;
+///
+/// client.send_to(b"hoid", socket.local_addr())?;
+///
+/// let mut buf = [0; 16];
+/// let (len, source) = socket.recv_from(&mut buf)?;
+/// assert_eq!(&buf[..len], b"hoid");
+/// assert_eq!(source, client.local_addr());
+/// # Ok(())}
+/// ```
+pub struct MemoryDatagramSocket {
+    /// Locked while receiving, so that the socket can be shared between threads
+    incoming: Mutex<Receiver<Datagram>>,
+    address: SocketAddr,
+    network: Weak<Network>,
+    peer: Mutex<Option<SocketAddr>>,
+    /// For a wildcard socket, the IP each sender last reached it at, which replies are sent from
+    reply_ips: Mutex<HashMap<SocketAddr, IpAddr>>,
+    read_timeout: Mutex<Option<Duration>>,
+    nonblocking: AtomicBool,
+}
+
+impl Drop for MemoryDatagramSocket {
+    fn drop(&mut self) {
+        Network::unbind_datagram(&self.network, &self.address);
+    }
+}
+
+impl MemoryDatagramSocket {
+    pub(crate) fn new(
+        incoming: Receiver<Datagram>,
+        address: SocketAddr,
+        network: Weak<Network>,
+    ) -> Self {
+        Self {
+            incoming: Mutex::new(incoming),
+            address,
+            network,
+            peer: Mutex::new(None),
+            reply_ips: Mutex::default(),
+            read_timeout: Mutex::new(None),
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
+    /// Creates a new `MemoryDatagramSocket` which will be bound to the specified address.
+    ///
+    /// Binding with a port number of `0` will request that a port be assigned to this socket. The
+    /// port allocated can be queried via the [`local_addr`] method.
+    ///
+    /// Binding to the unspecified IP creates a wildcard socket, which receives messages sent to
+    /// any IP on its port, following the same rules as [`MemoryListener::bind`]. Messages sent
+    /// from a wildcard socket come from the IP which their destination last reached it at, or
+    /// otherwise from the destination's own IP.
+    ///
+    /// [`local_addr`]: #method.local_addr
+    /// [`MemoryListener::bind`]: struct.MemoryListener.html#method.bind
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryDatagramSocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let socket = MemoryDatagramSocket::bind("192.51.100.2:0".parse().unwrap())?;
+    /// assert_ne!(socket.local_addr().port(), 0);
+    /// # Ok(())}
+    /// ```
+    pub fn bind(address: SocketAddr) -> Result<Self> {
+        MemoryNetwork::default_network().bind_datagram(address)
+    }
+
+    /// Returns the local address that this socket is bound to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryDatagramSocket;
+    /// use std::net::SocketAddr;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let socket = MemoryDatagramSocket::bind("192.51.100.2:5353".parse().unwrap())?;
+    ///
+    /// let expected: SocketAddr = "192.51.100.2:5353".parse().unwrap();
+    /// assert_eq!(socket.local_addr(), expected);
+    /// # Ok(())}
+    /// ```
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Returns the address of the peer this socket is connected to.
+    ///
+    /// If the socket isn't connected an error of kind `NotConnected` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::ErrorKind;
+    /// use memory_socket::MemoryDatagramSocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let socket = MemoryDatagramSocket::bind("192.51.100.2:0".parse().unwrap())?;
+    /// assert_eq!(socket.peer_addr().unwrap_err().kind(), ErrorKind::NotConnected);
+    ///
+    /// socket.connect("192.51.100.3:53".parse().unwrap())?;
+    /// assert_eq!(socket.peer_addr()?, "192.51.100.3:53".parse().unwrap());
+    /// # Ok(())}
+    /// ```
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.peer
+            .lock()
+            .unwrap()
+            .ok_or_else(|| ErrorKind::NotConnected.into())
+    }
+
+    /// Sends data on the socket to the given address, returning the number of bytes written.
+    ///
+    /// The whole of `buf` is always sent as a single message. If nothing is bound to `address`
+    /// the message is silently dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryDatagramSocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let socket = MemoryDatagramSocket::bind("192.51.100.2:0".parse().unwrap())?;
+    /// socket.send_to(b"lost", "192.51.100.9:9".parse().unwrap())?;
+    /// # Ok(())}
+    /// ```
+    pub fn send_to(&self, buf: &[u8], address: SocketAddr) -> Result<usize> {
+        let mut source = self.address;
+        if source.ip().is_unspecified() {
+            if let Some(ip) = self.reply_ips.lock().unwrap().get(&address) {
+                source.set_ip(*ip);
+            }
+        }
+        Network::send_datagram(
+            &self.network,
+            (source, address, Bytes::copy_from_slice(buf)),
+        );
+        Ok(buf.len())
+    }
+
+    /// Receives a single message on the socket, returning the number of bytes read and the
+    /// address the message originated from.
+    ///
+    /// If `buf` is too small to hold the message, the excess bytes are discarded.
+    ///
+    /// This function blocks until a message arrives, the read timeout elapses or, if the socket is
+    /// nonblocking, returns an error of kind `WouldBlock` immediately. If the socket has been
+    /// [connected], messages from any address other than the peer are discarded.
+    ///
+    /// [connected]: #method.connect
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryDatagramSocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let socket = MemoryDatagramSocket::bind("192.51.100.2:0".parse().unwrap())?;
+    /// socket.send_to(b"kaladin", socket.local_addr())?;
+    ///
+    /// // The buffer is too small, so the rest of the message is discarded
+    /// let mut buf = [0; 4];
+    /// let (len, _source) = socket.recv_from(&mut buf)?;
+    /// assert_eq!(&buf[..len], b"kala");
+    /// # Ok(())}
+    /// ```
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        let peer = *self.peer.lock().unwrap();
+        let deadline = self.deadline();
+
+        loop {
+            let (source, destination, payload) = self.recv_datagram(deadline)?;
+            if self.address.ip().is_unspecified() {
+                self.reply_ips
+                    .lock()
+                    .unwrap()
+                    .insert(source, destination.ip());
+            }
+            if peer.is_none() || peer == Some(source) {
+                let len = ::std::cmp::min(buf.len(), payload.len());
+                buf[..len].copy_from_slice(&payload[..len]);
+                return Ok((len, source));
+            }
+        }
+    }
+
+    /// Connects this socket to a remote address, allowing the [`send`] and [`recv`] methods to be
+    /// used and limiting received messages to those sent from that address.
+    ///
+    /// [`recv`]: #method.recv
+    /// [`send`]: #method.send
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryDatagramSocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let a = MemoryDatagramSocket::bind("192.51.100.2:0".parse().unwrap())?;
+    /// let b = MemoryDatagramSocket::bind("192.51.100.3:0".parse().unwrap())?;
+    /// a.connect(b.local_addr())?;
+    /// b.connect(a.local_addr())?;
+    ///
+    /// a.send(b"syl")?;
+    /// let mut buf = [0; 3];
+    /// assert_eq!(b.recv(&mut buf)?, 3);
+    /// # Ok(())}
+    /// ```
+    pub fn connect(&self, address: SocketAddr) -> Result<()> {
+        *self.peer.lock().unwrap() = Some(address);
+        Ok(())
+    }
+
+    /// Sends data on the socket to the connected peer, returning the number of bytes written.
+    ///
+    /// If the socket isn't connected an error of kind `NotConnected` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryDatagramSocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let socket = MemoryDatagramSocket::bind("192.51.100.2:0".parse().unwrap())?;
+    /// socket.connect("192.51.100.3:53".parse().unwrap())?;
+    /// socket.send(b"pattern")?;
+    /// # Ok(())}
+    /// ```
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        let peer = self.peer_addr()?;
+        self.send_to(buf, peer)
+    }
+
+    /// Receives a single message from the connected peer, returning the number of bytes read.
+    ///
+    /// If `buf` is too small to hold the message, the excess bytes are discarded. If the socket
+    /// isn't connected an error of kind `NotConnected` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryDatagramSocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let socket = MemoryDatagramSocket::bind("192.51.100.2:0".parse().unwrap())?;
+    /// socket.connect(socket.local_addr())?;
+    /// socket.send(b"wyndle")?;
+    ///
+    /// let mut buf = [0; 6];
+    /// assert_eq!(socket.recv(&mut buf)?, 6);
+    /// # Ok(())}
+    /// ```
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.peer_addr()?;
+        self.recv_from(buf).map(|(len, _source)| len)
+    }
+
+    /// Sets the read timeout to the timeout specified.
+    ///
+    /// If the value specified is `None`, then [`recv_from`] calls will block indefinitely. An
+    /// error of kind `InvalidInput` is returned if the zero `Duration` is passed to this method.
+    ///
+    /// [`recv_from`]: #method.recv_from
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{io::ErrorKind, time::Duration};
+    /// use memory_socket::MemoryDatagramSocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let socket = MemoryDatagramSocket::bind("192.51.100.2:0".parse().unwrap())?;
+    /// socket.set_read_timeout(Some(Duration::from_millis(10)))?;
+    ///
+    /// let mut buf = [0; 4];
+    /// let result = socket.recv_from(&mut buf);
+    /// assert_eq!(result.unwrap_err().kind(), ErrorKind::WouldBlock);
+    /// # Ok(())}
+    /// ```
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        *self.read_timeout.lock().unwrap() = check_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Returns the read timeout of this socket.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryDatagramSocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let socket = MemoryDatagramSocket::bind("192.51.100.2:0".parse().unwrap())?;
+    /// assert_eq!(socket.read_timeout()?, None);
+    /// # Ok(())}
+    /// ```
+    pub fn read_timeout(&self) -> Result<Option<Duration>> {
+        Ok(*self.read_timeout.lock().unwrap())
+    }
+
+    /// Moves this socket into or out of nonblocking mode.
+    ///
+    /// In nonblocking mode [`recv_from`] returns an error of kind `WouldBlock` if no message is
+    /// pending.
+    ///
+    /// [`recv_from`]: #method.recv_from
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::ErrorKind;
+    /// use memory_socket::MemoryDatagramSocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let socket = MemoryDatagramSocket::bind("192.51.100.2:0".parse().unwrap())?;
+    /// socket.set_nonblocking(true)?;
+    ///
+    /// let mut buf = [0; 4];
+    /// assert_eq!(socket.recv_from(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+    /// # Ok(())}
+    /// ```
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        crate::deadline(
+            self.nonblocking.load(Ordering::Relaxed),
+            *self.read_timeout.lock().unwrap(),
+        )
+    }
+
+    /// Wait for the next message until `deadline`, returning an error of kind `WouldBlock` once it
+    /// has passed.
+    fn recv_datagram(&self, deadline: Option<Instant>) -> Result<Datagram> {
+        use flume::RecvTimeoutError;
+
+        let incoming = self.incoming.lock().unwrap();
+        let result = match deadline {
+            Some(deadline) => incoming.recv_deadline(deadline),
+            None => incoming.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        // The sending side is only dropped when the network is torn down
+        result.map_err(|error| match error {
+            RecvTimeoutError::Timeout => ErrorKind::WouldBlock.into(),
+            RecvTimeoutError::Disconnected => ErrorKind::ConnectionAborted.into(),
+        })
+    }
+}
@@ -0,0 +1,131 @@
+use std::{net::SocketAddr, time::Duration};
+
+/// Controls when data written to a [`MemorySocket`] is sent to the peer without an explicit
+/// `flush`.
+///
+/// Writes to a `MemorySocket` are buffered, and how long they sit in the buffer is decided by its
+/// flush policy. The default, [`Nagle`], mirrors a `TcpStream`: data is sent right away when the
+/// peer has read everything sent before it and is otherwise coalesced with later writes for a
+/// short while. Calling `flush` always sends any buffered data immediately, whatever the policy.
+///
+/// The policy of a socket can be changed with [`MemorySocket::set_flush_policy`] or
+/// [`MemorySocket::set_nodelay`], or set for both sides of a pair with
+/// [`PairBuilder::flush_policy`].
+///
+/// [`MemorySocket`]: struct.MemorySocket.html
+/// [`MemorySocket::set_flush_policy`]: struct.MemorySocket.html#method.set_flush_policy
+/// [`MemorySocket::set_nodelay`]: struct.MemorySocket.html#method.set_nodelay
+/// [`Nagle`]: #variant.Nagle
+/// [`PairBuilder::flush_policy`]: struct.PairBuilder.html#method.flush_policy
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use memory_socket::{FlushPolicy, MemorySocket};
+///
+/// # fn main () -> ::std::io::Result<()> {
+/// let (mut socket_a, mut socket_b) = MemorySocket::new_pair();
+/// socket_a.set_flush_policy(FlushPolicy::EveryWrite);
+///
+/// // No flush is needed for the peer to see the data
+/// socket_a.write_all(b"hello")?;
+///
+/// let mut buf = [0; 5];
+/// socket_b.read_exact(&mut buf)?;
+/// # Ok(())}
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Data is only sent when the socket is explicitly flushed.
+    Manual,
+    /// Each write is sent immediately, as with `TCP_NODELAY`.
+    EveryWrite,
+    /// Data is sent once at least the given number of bytes are buffered.
+    Threshold(usize),
+    /// Small writes are coalesced in the manner of Nagle's algorithm.
+    ///
+    /// A write is sent immediately if the peer has read everything sent before it. Otherwise it
+    /// is held back until the peer catches up, a full segment has accumulated or `delay` has
+    /// passed, whichever comes first.
+    Nagle {
+        /// The longest data is held back for
+        delay: Duration,
+    },
+}
+
+impl FlushPolicy {
+    /// The delay used by the default `Nagle` policy, matching the delayed acknowledgement
+    /// timeout of common TCP implementations.
+    pub const DEFAULT_NAGLE_DELAY: Duration = Duration::from_millis(40);
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        FlushPolicy::Nagle {
+            delay: Self::DEFAULT_NAGLE_DELAY,
+        }
+    }
+}
+
+/// Controls what happens when a [`MemorySocket`] is dropped with written data which can no longer
+/// be delivered, such as when the peer has already hung up.
+///
+/// Data which is still buffered when a socket is dropped is normally sent to the peer before it
+/// observes EOF, just like closing a TCP socket. If the connection has already been closed or
+/// reset the data is lost instead, which usually points to a bug in the shutdown path of the
+/// code under test. Stricter policies help catch these bugs.
+///
+/// Data discarded by [`MemorySocket::abort`] is never reported.
+///
+/// [`MemorySocket`]: struct.MemorySocket.html
+/// [`MemorySocket::abort`]: struct.MemorySocket.html#method.abort
+///
+/// # Examples
+///
+/// ```should_panic
+/// use std::io::Write;
+/// use memory_socket::{DiscardPolicy, FlushPolicy, MemorySocket};
+///
+/// let (mut socket, peer) = MemorySocket::builder()
+///     .flush_policy(FlushPolicy::Manual)
+///     .discard_policy(DiscardPolicy::Panic)
+///     .pair();
+///
+/// socket.write_all(b"lost").unwrap();
+/// drop(peer);
+/// drop(socket); // panics
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DiscardPolicy {
+    /// Silently discard the data.
+    #[default]
+    Ignore,
+    /// Print a message to stderr.
+    Log,
+    /// Panic, unless the thread is already panicking.
+    Panic,
+}
+
+impl DiscardPolicy {
+    /// Report that `len` bytes written to the socket with the given `local` address were
+    /// discarded.
+    pub(crate) fn report(self, len: usize, local: Option<SocketAddr>) {
+        let socket = match local {
+            Some(local) => format!("socket {}", local),
+            None => "socket".to_owned(),
+        };
+        match self {
+            DiscardPolicy::Ignore => {}
+            DiscardPolicy::Log => eprintln!(
+                "memory-socket: {} dropped with {} bytes of undeliverable data",
+                socket, len
+            ),
+            DiscardPolicy::Panic if !std::thread::panicking() => panic!(
+                "{} dropped with {} bytes of undeliverable data",
+                socket, len
+            ),
+            DiscardPolicy::Panic => {}
+        }
+    }
+}
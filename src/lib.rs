@@ -2,36 +2,80 @@
 //!
 //! The `memory-socket` crate provides the [`MemoryListener`] and [`MemorySocket`] types which can
 //! be thought of as in-memory versions of the standard library `TcpListener` and `TcpStream`
-//! types.
+//! types, along with [`MemoryDatagramSocket`], an in-memory version of `UdpSocket`.
+//!
+//! Every listener and socket lives in a [`MemoryNetwork`]. The free-standing
+//! [`MemoryListener::bind`] and [`MemorySocket::connect`] functions use a process-wide default
+//! network, while separate `MemoryNetwork`s can be created to isolate independent sets of sockets
+//! from one another.
 //!
 //! ## Feature flags
 //!
-//! - `async`: Adds async support for [`MemorySocket`] and [`MemoryListener`]
+//! - `async`: Adds async support for [`MemorySocket`] and [`MemoryListener`] via the
+//!   `futures::io` traits
+//! - `tokio`: Adds async support for [`MemorySocket`] and [`MemoryListener`] via tokio's
+//!   `AsyncRead` and `AsyncWrite` traits
 //!
+//! [`MemoryDatagramSocket`]: struct.MemoryDatagramSocket.html
 //! [`MemoryListener`]: struct.MemoryListener.html
+//! [`MemoryListener::bind`]: struct.MemoryListener.html#method.bind
+//! [`MemoryNetwork`]: struct.MemoryNetwork.html
 //! [`MemorySocket`]: struct.MemorySocket.html
+//! [`MemorySocket::connect`]: struct.MemorySocket.html#method.connect
 
+use backlog::Backlog;
 use bytes::{buf::BufExt, Buf, Bytes, BytesMut};
-use flume::{Receiver, Sender};
-use once_cell::sync::Lazy;
+use network::{Network, PortReservation};
+use stats::Counters;
 use std::{
-    collections::HashMap,
     io::{ErrorKind, Read, Result, Write},
-    net::SocketAddr,
-    sync::Mutex,
+    net::{Shutdown, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant},
 };
 
-#[cfg(feature = "async")]
-mod r#async;
+mod backlog;
+mod builder;
+mod capture;
+mod datagram;
+mod flush;
+mod link;
+mod mock;
+mod network;
+mod partition;
+mod pipe;
+mod record;
+mod resolve;
+mod rng;
+mod segment;
+mod split;
+mod stats;
 
-#[cfg(feature = "async")]
-pub use r#async::IncomingStream;
+#[cfg(any(feature = "async", feature = "tokio"))]
+mod r#async;
+#[cfg(any(feature = "async", feature = "tokio"))]
+mod timer;
+#[cfg(feature = "tokio")]
+mod tokio_io;
 
-/// Collection of open connected sockets
-static SWITCHBOARD: Lazy<Mutex<SwitchBoard>> =
-    Lazy::new(|| Mutex::new(SwitchBoard(HashMap::default(), 1)));
+pub use builder::{ListenerBuilder, PairBuilder};
+pub use datagram::MemoryDatagramSocket;
+pub use flush::{DiscardPolicy, FlushPolicy};
+pub use link::LinkConfig;
+pub use mock::{MockHandle, MockPeer};
+pub use network::MemoryNetwork;
+pub use partition::{PartitionId, PartitionMode};
+pub use record::{Event, Recorder, Transcript};
+pub use resolve::ToMemoryAddrs;
+pub use segment::SegmentConfig;
+pub use split::{OwnedReadHalf, OwnedWriteHalf, ReadHalf, ReuniteError, WriteHalf};
+pub use stats::{ListenerStats, SocketStats};
 
-struct SwitchBoard(HashMap<SocketAddr, Sender<MemorySocket>>, u16);
+#[cfg(any(feature = "async", feature = "tokio"))]
+pub use r#async::{Accept, IncomingStream};
 
 /// An in-memory socket server, listening for connections.
 ///
@@ -70,20 +114,29 @@ struct SwitchBoard(HashMap<SocketAddr, Sender<MemorySocket>>, u16);
 /// }
 /// ```
 pub struct MemoryListener {
-    incoming: Receiver<MemorySocket>,
+    backlog: Arc<Backlog>,
     address: SocketAddr,
+    network: Weak<Network>,
+    nonblocking: AtomicBool,
 }
 
 impl Drop for MemoryListener {
     fn drop(&mut self) {
-        let mut switchboard = (&*SWITCHBOARD).lock().unwrap();
-        // Remove the Sending side of the channel in the switchboard when
-        // MemoryListener is dropped
-        switchboard.0.remove(&self.address);
+        Network::report_seed(&self.network);
+        self.close();
     }
 }
 
 impl MemoryListener {
+    pub(crate) fn new(backlog: Arc<Backlog>, address: SocketAddr, network: Weak<Network>) -> Self {
+        Self {
+            backlog,
+            address,
+            network,
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
     /// Creates a new `MemoryListener` which will be bound to the specified
     /// port.
     ///
@@ -93,6 +146,12 @@ impl MemoryListener {
     /// to this listener. The port allocated can be queried via the
     /// [`local_addr`] method.
     ///
+    /// Binding to the unspecified IP, `0.0.0.0` or `[::]`, creates a wildcard
+    /// listener which accepts connections to any IP on its port. As with the
+    /// OS, a listener bound to the exact address being connected to takes
+    /// precedence, followed by a wildcard of the same family and finally a
+    /// dual-stack listener bound to `[::]`.
+    ///
     /// [`local_addr`]: #method.local_addr
     ///
     /// # Examples
@@ -106,42 +165,46 @@ impl MemoryListener {
     /// let listener = MemoryListener::bind("192.51.100.2:1337".parse().unwrap())?;
     /// # Ok(())}
     /// ```
-    pub fn bind(mut address: SocketAddr) -> Result<Self> {
-        let mut switchboard = (&*SWITCHBOARD).lock().unwrap();
-
-        // It doesn't make sense to listen on "all interfaces" as memory socket
-        // can mimic all potential addresses. Raise an error rather than
-        // trying to make something up.
-        if address.ip().is_unspecified() {
-            return Err(ErrorKind::AddrNotAvailable.into());
-        }
-
-        // If they didn't provide a port find one that isn't in use.
-        if address.port() == 0 {
-            let start_port = switchboard.1;
-            address.set_port(switchboard.1);
-            while switchboard.0.contains_key(&address) {
-                switchboard.1 += 1;
-                if switchboard.1 == std::u16::MAX {
-                    switchboard.1 = 1;
-                }
-                if switchboard.1 == start_port {
-                    return Err(ErrorKind::AddrInUse.into());
-                }
-                address.set_port(switchboard.1);
-            }
-        } else if switchboard.0.contains_key(&address) {
-            // Can't listen on the same address and port twice
-            return Err(ErrorKind::AddrInUse.into());
-        }
+    pub fn bind(address: SocketAddr) -> Result<Self> {
+        MemoryNetwork::default_network().bind(address)
+    }
 
-        let (sender, receiver) = flume::unbounded();
-        switchboard.0.insert(address, sender);
+    /// Creates a new `MemoryListener` bound to the first address `address` resolves to which can
+    /// be bound, looking up host names in the default network.
+    ///
+    /// See [`MemoryNetwork::register_host`] for registering host names.
+    ///
+    /// [`MemoryNetwork::register_host`]: struct.MemoryNetwork.html#method.register_host
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryListener;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let listener = MemoryListener::bind_to("192.51.100.2:1340")?;
+    /// # Ok(())}
+    /// ```
+    pub fn bind_to<A: ToMemoryAddrs>(address: A) -> Result<Self> {
+        MemoryNetwork::default_network().bind_to(address)
+    }
 
-        Ok(Self {
-            incoming: receiver,
-            address,
-        })
+    /// Returns a new builder for configuring a listener, such as limiting its backlog, before
+    /// binding it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryListener;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let listener = MemoryListener::builder()
+    ///     .backlog(128)
+    ///     .bind("192.51.100.2:1339".parse().unwrap())?;
+    /// # Ok(())}
+    /// ```
+    pub fn builder() -> ListenerBuilder {
+        ListenerBuilder::new()
     }
 
     /// Returns the local address that this listener is bound to.
@@ -166,13 +229,38 @@ impl MemoryListener {
         self.address
     }
 
+    /// Returns a snapshot of the traffic on the connections accepted by this listener, including
+    /// those which have since been closed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use memory_socket::{MemoryListener, MemorySocket};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let listener = MemoryListener::bind("192.51.100.2:64".parse().unwrap())?;
+    /// let _socket = MemorySocket::connect(listener.local_addr())?;
+    /// let mut accepted = listener.accept()?;
+    /// accepted.write_all(b"hello")?;
+    ///
+    /// let stats = listener.stats();
+    /// assert_eq!(stats.accepted(), 1);
+    /// assert_eq!(stats.bytes_written(), 5);
+    /// # Ok(())}
+    /// ```
+    pub fn stats(&self) -> ListenerStats {
+        self.backlog.stats()
+    }
+
     /// Returns an iterator over the connections being received on this
     /// listener.
     ///
-    /// The returned iterator will never return `None`. Iterating over
-    /// it is equivalent to calling [`accept`] in a loop.
+    /// Iterating over the returned iterator is equivalent to calling [`accept`] in a loop, until
+    /// the listener is [closed] at which point the iterator ends.
     ///
     /// [`accept`]: #method.accept
+    /// [closed]: #method.close
     ///
     /// # Examples
     ///
@@ -201,6 +289,18 @@ impl MemoryListener {
     /// is established. When established, the corresponding [`MemorySocket`]
     /// will be returned.
     ///
+    /// If the listener has been put into nonblocking mode via
+    /// [`set_nonblocking`] and no connection is pending, an error of kind
+    /// `WouldBlock` is returned instead.
+    ///
+    /// If the listener is [closed], or the [`MemoryNetwork`] it was bound in
+    /// is dropped, an error of kind `ConnectionAborted` is returned.
+    ///
+    /// [closed]: #method.close
+    ///
+    /// [`set_nonblocking`]: #method.set_nonblocking
+    ///
+    /// [`MemoryNetwork`]: struct.MemoryNetwork.html
     /// [`MemorySocket`]: struct.MemorySocket.html
     ///
     /// # Examples
@@ -216,11 +316,179 @@ impl MemoryListener {
     /// }
     /// ```
     pub fn accept(&self) -> Result<MemorySocket> {
-        self.incoming.iter().next().ok_or_else(|| unreachable!())
+        if self.nonblocking.load(Ordering::Relaxed) {
+            return self.try_accept();
+        }
+
+        self.backlog.pop(None)
+    }
+
+    /// Accept a new incoming connection from this listener, waiting at most `timeout` for one to
+    /// be established.
+    ///
+    /// If no connection is established before the timeout elapses an error of kind `TimedOut` is
+    /// returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{io::ErrorKind, time::Duration};
+    /// use memory_socket::MemoryListener;
+    ///
+    /// let listener = MemoryListener::bind("192.51.100.2:8081".parse().unwrap()).unwrap();
+    /// let result = listener.accept_timeout(Duration::from_millis(10));
+    /// assert_eq!(result.err().map(|e| e.kind()), Some(ErrorKind::TimedOut));
+    /// ```
+    pub fn accept_timeout(&self, timeout: Duration) -> Result<MemorySocket> {
+        self.backlog
+            .pop(Some(Instant::now() + timeout))
+            .map_err(|error| match error.kind() {
+                ErrorKind::WouldBlock => ErrorKind::TimedOut.into(),
+                _ => error,
+            })
+    }
+
+    /// Moves this listener into or out of nonblocking mode.
+    ///
+    /// This will result in the [`accept`] operation becoming nonblocking, i.e., immediately
+    /// returning from its call. If no connection is pending, an error of kind `WouldBlock` is
+    /// returned.
+    ///
+    /// [`accept`]: #method.accept
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::ErrorKind;
+    /// use memory_socket::MemoryListener;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let listener = MemoryListener::bind("192.51.100.2:8083".parse().unwrap())?;
+    /// listener.set_nonblocking(true)?;
+    ///
+    /// for stream in listener.incoming() {
+    ///     match stream {
+    ///         Ok(_socket) => { /* handle the connection */ }
+    ///         Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+    ///             // wait until the listener has a connection pending
+    ///             # break;
+    ///         }
+    ///         Err(e) => panic!("encountered IO error: {}", e),
+    ///     }
+    /// }
+    /// # Ok(())}
+    /// ```
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Condition the data flowing over connections made to this listener with `link`, or stop
+    /// conditioning them if `None` is given.
+    ///
+    /// Only connections made after this call are affected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_socket::{LinkConfig, MemoryListener};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let listener = MemoryListener::bind("192.51.100.2:8084".parse().unwrap())?;
+    /// listener.set_link(Some(LinkConfig::new().bandwidth(64 * 1024)));
+    /// # Ok(())}
+    /// ```
+    pub fn set_link(&self, link: Option<LinkConfig>) {
+        Network::set_link(&self.network, self.address, &self.backlog, link);
+    }
+
+    /// Close this listener, releasing its address.
+    ///
+    /// Any threads or tasks blocked in [`accept`] are woken with an error of kind
+    /// `ConnectionAborted`, and iterators and streams over incoming connections end. Connections
+    /// which were queued but never accepted are reset, so their peers observe an error of kind
+    /// `ConnectionReset` rather than waiting forever.
+    ///
+    /// Closing happens automatically when the listener is dropped. Use [`close_handle`] to close
+    /// the listener from elsewhere.
+    ///
+    /// [`accept`]: #method.accept
+    /// [`close_handle`]: #method.close_handle
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::ErrorKind;
+    /// use memory_socket::MemoryListener;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let listener = MemoryListener::bind("192.51.100.2:8085".parse().unwrap())?;
+    /// listener.close();
+    ///
+    /// assert_eq!(listener.accept().err().map(|e| e.kind()), Some(ErrorKind::ConnectionAborted));
+    /// assert!(listener.incoming().next().is_none());
+    /// # Ok(())}
+    /// ```
+    pub fn close(&self) {
+        Network::close_listener(&self.network, &self.address, &self.backlog);
+    }
+
+    /// Returns a handle which can be used to [close] this listener from another thread or task.
+    ///
+    /// [close]: #method.close
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryListener;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let listener = MemoryListener::bind("192.51.100.2:8086".parse().unwrap())?;
+    /// let handle = listener.close_handle();
+    ///
+    /// std::thread::spawn(move || handle.close());
+    /// for stream in listener.incoming() {
+    ///     // handle the connection
+    ///     # stream?;
+    /// }
+    /// # Ok(())}
+    /// ```
+    pub fn close_handle(&self) -> CloseHandle {
+        CloseHandle {
+            backlog: self.backlog.clone(),
+            address: self.address,
+            network: self.network.clone(),
+        }
+    }
+
+    /// Attempt to accept a new incoming connection from this listener without blocking.
+    ///
+    /// If no connection is pending an error of kind `WouldBlock` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::ErrorKind;
+    /// use memory_socket::{MemoryListener, MemorySocket};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let listener = MemoryListener::bind("192.51.100.2:8082".parse().unwrap())?;
+    /// assert_eq!(
+    ///     listener.try_accept().err().map(|e| e.kind()),
+    ///     Some(ErrorKind::WouldBlock)
+    /// );
+    ///
+    /// let _dialer = MemorySocket::connect(listener.local_addr())?;
+    /// let _socket = listener.try_accept()?;
+    /// # Ok(())}
+    /// ```
+    pub fn try_accept(&self) -> Result<MemorySocket> {
+        self.backlog.pop(Some(Instant::now()))
     }
 }
 
-/// An iterator that infinitely [`accept`]s connections on a [`MemoryListener`].
+/// An iterator that [`accept`]s connections on a [`MemoryListener`] until it is closed.
 ///
 /// This `struct` is created by the [`incoming`] method on [`MemoryListener`].
 /// See its documentation for more info.
@@ -236,7 +504,33 @@ impl<'a> Iterator for Incoming<'a> {
     type Item = Result<MemorySocket>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        Some(self.inner.accept())
+        match self.inner.accept() {
+            Err(_) if self.inner.backlog.is_closed() => None,
+            result => Some(result),
+        }
+    }
+}
+
+/// A handle used to close a [`MemoryListener`] from another thread or task.
+///
+/// This `struct` is created by the [`close_handle`] method on [`MemoryListener`].
+/// See its documentation for more info.
+///
+/// [`close_handle`]: struct.MemoryListener.html#method.close_handle
+/// [`MemoryListener`]: struct.MemoryListener.html
+#[derive(Clone)]
+pub struct CloseHandle {
+    backlog: Arc<Backlog>,
+    address: SocketAddr,
+    network: Weak<Network>,
+}
+
+impl CloseHandle {
+    /// Close the listener, see [`MemoryListener::close`].
+    ///
+    /// [`MemoryListener::close`]: struct.MemoryListener.html#method.close
+    pub fn close(&self) {
+        Network::close_listener(&self.network, &self.address, &self.backlog);
     }
 }
 
@@ -269,24 +563,39 @@ impl<'a> Iterator for Incoming<'a> {
 /// [accepting]: struct.MemoryListener.html#method.accept
 /// [listener]: struct.MemoryListener.html
 pub struct MemorySocket {
-    incoming: Receiver<Bytes>,
-    outgoing: Sender<Bytes>,
-    write_buffer: BytesMut,
-    current_buffer: Option<Bytes>,
-    seen_eof: bool,
+    reader: Reader,
+    writer: Writer,
 }
 
 impl MemorySocket {
-    fn new(incoming: Receiver<Bytes>, outgoing: Sender<Bytes>) -> Self {
+    pub(crate) fn new(incoming: pipe::Receiver, outgoing: pipe::Sender) -> Self {
+        let endpoints = Arc::new(Endpoints::default());
+        let counters = Arc::new(Counters::default());
         Self {
-            incoming,
-            outgoing,
-            write_buffer: BytesMut::new(),
-            current_buffer: None,
-            seen_eof: false,
+            reader: Reader::new(incoming, endpoints.clone(), counters.clone()),
+            writer: Writer::new(outgoing, endpoints, counters),
         }
     }
 
+    /// Associate addresses with both halves of this socket.
+    pub(crate) fn set_endpoints(&mut self, endpoints: Endpoints) {
+        let endpoints = Arc::new(endpoints);
+        self.reader.endpoints = endpoints.clone();
+        self.writer.endpoints = endpoints;
+    }
+
+    /// Mark this socket's randomness as derived from the process seed.
+    pub(crate) fn set_process_seeded(&mut self) {
+        self.writer.process_seeded = true;
+    }
+
+    /// Replace the traffic counters shared by both halves of this socket.
+    pub(crate) fn set_counters(&mut self, counters: Counters) {
+        let counters = Arc::new(counters);
+        self.reader.counters = counters.clone();
+        self.writer.counters = counters;
+    }
+
     /// Construct both sides of an in-memory socket.
     ///
     /// # Examples
@@ -297,12 +606,45 @@ impl MemorySocket {
     /// let (socket_a, socket_b) = MemorySocket::new_pair();
     /// ```
     pub fn new_pair() -> (Self, Self) {
-        let (a_tx, a_rx) = flume::unbounded();
-        let (b_tx, b_rx) = flume::unbounded();
-        let a = Self::new(a_rx, b_tx);
-        let b = Self::new(b_rx, a_tx);
+        Self::builder().pair()
+    }
 
-        (a, b)
+    /// Construct both sides of an in-memory socket where at most `capacity` bytes can be in
+    /// flight in each direction.
+    ///
+    /// Flushing more data than the peer has room for will block until the peer reads enough of
+    /// the data already in flight. This is a shorthand for
+    /// `MemorySocket::builder().capacity(capacity).pair()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is `0`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemorySocket;
+    ///
+    /// let (socket_a, socket_b) = MemorySocket::pair_with_capacity(1024);
+    /// ```
+    pub fn pair_with_capacity(capacity: usize) -> (Self, Self) {
+        Self::builder().capacity(capacity).pair()
+    }
+
+    /// Returns a [`PairBuilder`] which can be used to configure both sides of an in-memory
+    /// socket.
+    ///
+    /// [`PairBuilder`]: struct.PairBuilder.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemorySocket;
+    ///
+    /// let (socket_a, socket_b) = MemorySocket::builder().capacity(1024).pair();
+    /// ```
+    pub fn builder() -> PairBuilder {
+        PairBuilder::new()
     }
 
     /// Create a new in-memory Socket connected to the specified port.
@@ -310,6 +652,19 @@ impl MemorySocket {
     /// This function will create a new MemorySocket socket and attempt to connect it to
     /// the `port` provided.
     ///
+    /// The connection originates from an ephemeral port on the same IP address as the one being
+    /// connected to, as though it were made over a loopback interface. Use [`connect_from`] to
+    /// pick a different local address.
+    ///
+    /// # Errors
+    ///
+    /// As with `TcpStream`, an error of kind `ConnectionRefused` is returned if nothing is
+    /// listening on `address`, or if its listener is closed before the connection could be
+    /// queued. An error of kind `AddrNotAvailable` is only returned if `address` could never be
+    /// reached, i.e. its IP is unspecified or its port is `0`.
+    ///
+    /// [`connect_from`]: #method.connect_from
+    ///
     /// # Examples
     ///
     /// ```
@@ -321,24 +676,615 @@ impl MemorySocket {
     /// # Ok(())}
     /// ```
     pub fn connect(address: SocketAddr) -> Result<MemorySocket> {
-        let mut switchboard = (&*SWITCHBOARD).lock().unwrap();
-        match switchboard.0.get_mut(&address) {
-            Some(sender) => {
-                let (socket_a, socket_b) = Self::new_pair();
-                // Send the socket to the listener
-                sender
-                    .send(socket_a)
-                    .map_err(|_| ErrorKind::AddrNotAvailable)?;
-
-                Ok(socket_b)
-            }
-            None => Err(ErrorKind::AddrNotAvailable.into()),
+        MemoryNetwork::default_network().connect(address)
+    }
+
+    /// Create a new in-memory Socket connected to `address`, trying each of the addresses it
+    /// resolves to in turn until one succeeds.
+    ///
+    /// Host names are looked up in the registry of the default network, see
+    /// [`MemoryNetwork::register_host`].
+    ///
+    /// [`MemoryNetwork::register_host`]: struct.MemoryNetwork.html#method.register_host
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{MemoryNetwork, MemorySocket};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// MemoryNetwork::default_network().register_host("api.internal", "192.51.100.6".parse().unwrap());
+    /// # let _listener = memory_socket::MemoryListener::bind("192.51.100.6:443".parse().unwrap())?;
+    /// let socket = MemorySocket::connect_to("api.internal:443")?;
+    /// # Ok(())}
+    /// ```
+    pub fn connect_to<A: ToMemoryAddrs>(address: A) -> Result<MemorySocket> {
+        MemoryNetwork::default_network().connect_to(address)
+    }
+
+    /// Create a new in-memory Socket connected to the specified address, waiting up to `timeout`
+    /// for room in the listener's backlog.
+    ///
+    /// Where [`connect`] fails immediately with an error of kind `ConnectionRefused` when the
+    /// listener's [backlog] is full, this waits for a pending connection to be accepted. If none is
+    /// accepted before the timeout elapses an error of kind `TimedOut` is returned. An error of
+    /// kind `InvalidInput` is returned if the zero `Duration` is passed to this method.
+    ///
+    /// [backlog]: struct.ListenerBuilder.html#method.backlog
+    /// [`connect`]: #method.connect
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{io::ErrorKind, time::Duration};
+    /// use memory_socket::{MemoryListener, MemorySocket};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let listener = MemoryListener::builder()
+    ///     .backlog(1)
+    ///     .bind("192.51.100.2:64".parse().unwrap())?;
+    /// let _first = MemorySocket::connect(listener.local_addr())?;
+    ///
+    /// let result = MemorySocket::connect_timeout(listener.local_addr(), Duration::from_millis(10));
+    /// assert_eq!(result.err().map(|e| e.kind()), Some(ErrorKind::TimedOut));
+    /// # Ok(())}
+    /// ```
+    pub fn connect_timeout(address: SocketAddr, timeout: Duration) -> Result<MemorySocket> {
+        MemoryNetwork::default_network().connect_timeout(address, timeout)
+    }
+
+    /// Create a new in-memory Socket connected to the specified address, originating from
+    /// `local_address`.
+    ///
+    /// If the port of `local_address` is `0` an unused ephemeral port will be assigned, and if
+    /// its IP is unspecified the IP being connected to will be used. The local port remains
+    /// reserved for as long as the returned socket is open.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemorySocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// # let _listener = memory_socket::MemoryListener::bind("192.51.100.2:61".parse().unwrap())?;
+    /// let socket = MemorySocket::connect_from(
+    ///     "192.51.100.7:0".parse().unwrap(),
+    ///     "192.51.100.2:61".parse().unwrap(),
+    /// )?;
+    /// assert_eq!(socket.local_addr()?.ip(), "192.51.100.7".parse::<std::net::IpAddr>().unwrap());
+    /// # Ok(())}
+    /// ```
+    pub fn connect_from(local_address: SocketAddr, address: SocketAddr) -> Result<MemorySocket> {
+        MemoryNetwork::default_network().connect_from(local_address, address)
+    }
+
+    /// Create a new in-memory Socket connected to the specified address, with the data flowing
+    /// in each direction conditioned by `link`.
+    ///
+    /// The given link takes precedence over any set on the listener being connected to with
+    /// [`MemoryListener::set_link`].
+    ///
+    /// [`MemoryListener::set_link`]: struct.MemoryListener.html#method.set_link
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_socket::{LinkConfig, MemorySocket};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// # let _listener = memory_socket::MemoryListener::bind("192.51.100.2:63".parse().unwrap())?;
+    /// let link = LinkConfig::new().latency(Duration::from_millis(10));
+    /// let socket = MemorySocket::connect_with_link("192.51.100.2:63".parse().unwrap(), link)?;
+    /// # Ok(())}
+    /// ```
+    pub fn connect_with_link(address: SocketAddr, link: LinkConfig) -> Result<MemorySocket> {
+        MemoryNetwork::default_network().connect_with_link(address, link)
+    }
+
+    /// Returns the socket address of the local half of this connection.
+    ///
+    /// For a socket returned by [`connect`] this is the ephemeral address the connection
+    /// originates from, while for a socket returned by [`accept`] it is the address of the
+    /// listener. Sockets constructed via [`new_pair`] have no address and return an error of
+    /// kind `AddrNotAvailable`.
+    ///
+    /// [`accept`]: struct.MemoryListener.html#method.accept
+    /// [`connect`]: #method.connect
+    /// [`new_pair`]: #method.new_pair
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{MemoryListener, MemorySocket};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let listener = MemoryListener::bind("192.51.100.2:62".parse().unwrap())?;
+    /// let socket = MemorySocket::connect(listener.local_addr())?;
+    /// let accepted = listener.accept()?;
+    ///
+    /// assert_eq!(accepted.local_addr()?, listener.local_addr());
+    /// assert_eq!(accepted.peer_addr()?, socket.local_addr()?);
+    /// # Ok(())}
+    /// ```
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.reader.endpoints.local_addr()
+    }
+
+    /// Returns the socket address of the remote peer of this connection.
+    ///
+    /// Sockets constructed via [`new_pair`] have no address and return an error of kind
+    /// `AddrNotAvailable`.
+    ///
+    /// [`new_pair`]: #method.new_pair
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{MemoryListener, MemorySocket};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let listener = MemoryListener::bind("192.51.100.2:63".parse().unwrap())?;
+    /// let socket = MemorySocket::connect(listener.local_addr())?;
+    ///
+    /// assert_eq!(socket.peer_addr()?, listener.local_addr());
+    /// # Ok(())}
+    /// ```
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.reader.endpoints.peer_addr()
+    }
+
+    /// Returns a snapshot of the traffic on this socket.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Read, Write};
+    /// use memory_socket::MemorySocket;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let (mut socket_a, mut socket_b) = MemorySocket::new_pair();
+    /// socket_a.write_all(b"hello")?;
+    /// socket_a.flush()?;
+    ///
+    /// let mut buf = [0; 5];
+    /// socket_b.read_exact(&mut buf)?;
+    ///
+    /// let stats = socket_a.stats();
+    /// assert_eq!(stats.bytes_written(), 5);
+    /// assert_eq!(stats.flushes(), 1);
+    /// assert_eq!(socket_b.stats().bytes_read(), 5);
+    /// # Ok(())}
+    /// ```
+    pub fn stats(&self) -> SocketStats {
+        let (flushes, max_queued) = self.writer.outgoing.stats();
+        self.reader.counters.snapshot(flushes, max_queued)
+    }
+
+    /// Abruptly terminate this connection, as though a TCP RST had been sent.
+    ///
+    /// Unlike dropping the socket or shutting it down, any data in flight in either direction is
+    /// discarded and the peer's reads and writes fail with an error of kind `ConnectionReset`
+    /// rather than observing a graceful EOF.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{ErrorKind, Read};
+    /// use memory_socket::MemorySocket;
+    ///
+    /// let (socket, mut peer) = MemorySocket::new_pair();
+    /// socket.abort();
+    ///
+    /// let mut buf = [0; 4];
+    /// assert_eq!(peer.read(&mut buf).unwrap_err().kind(), ErrorKind::ConnectionReset);
+    /// ```
+    pub fn abort(mut self) {
+        // Buffered data is discarded deliberately, so it isn't reported when the writer drops
+        self.writer.write_buffer.clear();
+        self.reader.incoming.reset();
+        self.writer.outgoing.reset();
+    }
+
+    /// Shuts down the read, write, or both halves of this connection.
+    ///
+    /// This function will cause all pending and future I/O on the specified portions to return
+    /// immediately with an appropriate value (see the documentation of `Shutdown`).
+    ///
+    /// Shutting down the write half first flushes any buffered data, after which the peer will
+    /// observe EOF once it has read everything sent so far. Shutting down the read half discards
+    /// any data in flight and causes further reads to return `Ok(0)` while the peer's writes fail
+    /// with `BrokenPipe`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{
+    ///     io::{Read, Result, Write},
+    ///     net::Shutdown,
+    /// };
+    /// use memory_socket::MemorySocket;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let (mut client, mut server) = MemorySocket::new_pair();
+    ///
+    /// client.write_all(b"request")?;
+    /// client.shutdown(Shutdown::Write)?;
+    ///
+    /// let mut request = Vec::new();
+    /// server.read_to_end(&mut request)?;
+    /// server.write_all(b"response")?;
+    /// server.flush()?;
+    ///
+    /// let mut buf = [0; 8];
+    /// client.read_exact(&mut buf)?;
+    /// assert_eq!(&buf, b"response");
+    /// # Ok(())}
+    /// ```
+    pub fn shutdown(&mut self, how: Shutdown) -> Result<()> {
+        if let Shutdown::Write | Shutdown::Both = how {
+            self.writer.shutdown()?;
+        }
+
+        if let Shutdown::Read | Shutdown::Both = how {
+            self.reader.incoming.close();
         }
+
+        Ok(())
+    }
+
+    /// Sets the read timeout to the timeout specified.
+    ///
+    /// If the value specified is `None`, then `read` calls will block indefinitely. An error of
+    /// kind `InvalidInput` is returned if the zero `Duration` is passed to this method.
+    ///
+    /// When a read times out an error of kind `WouldBlock` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{
+    ///     io::{ErrorKind, Read},
+    ///     time::Duration,
+    /// };
+    /// use memory_socket::MemorySocket;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let (mut socket, _peer) = MemorySocket::new_pair();
+    /// socket.set_read_timeout(Some(Duration::from_millis(10)))?;
+    ///
+    /// let mut buf = [0; 10];
+    /// let error = socket.read(&mut buf).unwrap_err();
+    /// assert_eq!(error.kind(), ErrorKind::WouldBlock);
+    /// # Ok(())}
+    /// ```
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.reader.timeout = check_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Returns the read timeout of this socket.
+    ///
+    /// If the timeout is `None`, then `read` calls will block indefinitely.
+    pub fn read_timeout(&self) -> Result<Option<Duration>> {
+        Ok(self.reader.timeout)
+    }
+
+    /// Sets the write timeout to the timeout specified.
+    ///
+    /// Writes only block when the socket was constructed with a bounded [`capacity`] and the peer
+    /// isn't keeping up. If the value specified is `None`, then `write` and `flush` calls will
+    /// block indefinitely. An error of kind `InvalidInput` is returned if the zero `Duration` is
+    /// passed to this method.
+    ///
+    /// When a write times out an error of kind `WouldBlock` is returned and any data which could
+    /// not be sent remains buffered.
+    ///
+    /// [`capacity`]: struct.PairBuilder.html#method.capacity
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{
+    ///     io::{ErrorKind, Write},
+    ///     time::Duration,
+    /// };
+    /// use memory_socket::MemorySocket;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let (mut socket, _peer) = MemorySocket::pair_with_capacity(4);
+    /// socket.set_write_timeout(Some(Duration::from_millis(10)))?;
+    ///
+    /// socket.write_all(b"stormlight")?;
+    /// let error = socket.flush().unwrap_err();
+    /// assert_eq!(error.kind(), ErrorKind::WouldBlock);
+    /// # Ok(())}
+    /// ```
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.writer.timeout = check_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Returns the write timeout of this socket.
+    ///
+    /// If the timeout is `None`, then `write` and `flush` calls will block indefinitely.
+    pub fn write_timeout(&self) -> Result<Option<Duration>> {
+        Ok(self.writer.timeout)
+    }
+
+    /// Sets when data written to this socket is sent to the peer without an explicit `flush`.
+    ///
+    /// See [`FlushPolicy`] for the available policies.
+    ///
+    /// [`FlushPolicy`]: enum.FlushPolicy.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{FlushPolicy, MemorySocket};
+    ///
+    /// let (mut socket, _peer) = MemorySocket::new_pair();
+    /// socket.set_flush_policy(FlushPolicy::Threshold(1024));
+    /// assert_eq!(socket.flush_policy(), FlushPolicy::Threshold(1024));
+    /// ```
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.writer.policy = policy;
+    }
+
+    /// Returns the flush policy of this socket.
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.writer.policy
+    }
+
+    /// Sets what happens when this socket is dropped with written data which can no longer be
+    /// delivered to the peer.
+    ///
+    /// See [`DiscardPolicy`] for more details.
+    ///
+    /// [`DiscardPolicy`]: enum.DiscardPolicy.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{DiscardPolicy, MemorySocket};
+    ///
+    /// let (mut socket, _peer) = MemorySocket::new_pair();
+    /// socket.set_discard_policy(DiscardPolicy::Log);
+    /// assert_eq!(socket.discard_policy(), DiscardPolicy::Log);
+    /// ```
+    pub fn set_discard_policy(&mut self, policy: DiscardPolicy) {
+        self.writer.discard_policy = policy;
+    }
+
+    /// Returns the discard policy of this socket.
+    pub fn discard_policy(&self) -> DiscardPolicy {
+        self.writer.discard_policy
+    }
+
+    /// Sets the value of the `TCP_NODELAY` option on this socket.
+    ///
+    /// If set, each write is sent to the peer immediately, as with [`FlushPolicy::EveryWrite`].
+    /// If not set, writes are coalesced using the default [`FlushPolicy::Nagle`] policy.
+    ///
+    /// [`FlushPolicy::EveryWrite`]: enum.FlushPolicy.html#variant.EveryWrite
+    /// [`FlushPolicy::Nagle`]: enum.FlushPolicy.html#variant.Nagle
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemorySocket;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let (mut socket, _peer) = MemorySocket::new_pair();
+    /// socket.set_nodelay(true)?;
+    /// assert!(socket.nodelay()?);
+    /// # Ok(())}
+    /// ```
+    pub fn set_nodelay(&mut self, nodelay: bool) -> Result<()> {
+        self.writer.policy = if nodelay {
+            FlushPolicy::EveryWrite
+        } else {
+            FlushPolicy::default()
+        };
+        Ok(())
+    }
+
+    /// Gets the value of the `TCP_NODELAY` option on this socket, which is set if its flush
+    /// policy is [`FlushPolicy::EveryWrite`].
+    ///
+    /// [`FlushPolicy::EveryWrite`]: enum.FlushPolicy.html#variant.EveryWrite
+    pub fn nodelay(&self) -> Result<bool> {
+        Ok(self.writer.policy == FlushPolicy::EveryWrite)
+    }
+
+    /// Moves this socket into or out of nonblocking mode.
+    ///
+    /// This will result in `read`, `write` and `flush` operations becoming nonblocking, i.e.,
+    /// immediately returning from their calls. If the IO operation is successful, `Ok` is
+    /// returned and no further action is required. If the IO operation could not be completed
+    /// and needs to be retried, an error with kind `WouldBlock` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{ErrorKind, Read, Write};
+    /// use memory_socket::MemorySocket;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let (mut socket, mut peer) = MemorySocket::new_pair();
+    /// socket.set_nonblocking(true)?;
+    ///
+    /// let mut buf = [0; 5];
+    /// assert_eq!(socket.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+    ///
+    /// peer.write_all(b"shard")?;
+    /// peer.flush()?;
+    /// assert_eq!(socket.read(&mut buf)?, 5);
+    /// # Ok(())}
+    /// ```
+    pub fn set_nonblocking(&mut self, nonblocking: bool) -> Result<()> {
+        self.reader.nonblocking = nonblocking;
+        self.writer.nonblocking = nonblocking;
+        Ok(())
+    }
+
+    /// Splits a `MemorySocket` into a read half and a write half, which can be used to read and
+    /// write the socket concurrently.
+    ///
+    /// The halves borrow the socket, see [`into_split`] for halves which can be moved
+    /// independently.
+    ///
+    /// [`into_split`]: #method.into_split
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Read, Result, Write};
+    /// use memory_socket::MemorySocket;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let (mut socket, mut peer) = MemorySocket::new_pair();
+    /// let (mut read_half, mut write_half) = socket.split();
+    ///
+    /// write_half.write_all(b"ping")?;
+    /// write_half.flush()?;
+    ///
+    /// let mut buf = [0; 4];
+    /// peer.read_exact(&mut buf)?;
+    /// peer.write_all(&buf)?;
+    /// peer.flush()?;
+    ///
+    /// read_half.read_exact(&mut buf)?;
+    /// assert_eq!(&buf, b"ping");
+    /// # Ok(())}
+    /// ```
+    pub fn split(&mut self) -> (ReadHalf<'_>, WriteHalf<'_>) {
+        (
+            ReadHalf {
+                reader: &mut self.reader,
+            },
+            WriteHalf {
+                writer: &mut self.writer,
+            },
+        )
+    }
+
+    /// Splits a `MemorySocket` into a read half and a write half, which can be used to read and
+    /// write the socket concurrently from different threads or tasks.
+    ///
+    /// Dropping the [`OwnedWriteHalf`] shuts down the write half of the connection, and the
+    /// halves can be put back together with [`OwnedReadHalf::reunite`].
+    ///
+    /// [`OwnedReadHalf::reunite`]: struct.OwnedReadHalf.html#method.reunite
+    /// [`OwnedWriteHalf`]: struct.OwnedWriteHalf.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Read, Result, Write};
+    /// use memory_socket::MemorySocket;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let (socket, mut peer) = MemorySocket::new_pair();
+    /// let (mut read_half, mut write_half) = socket.into_split();
+    ///
+    /// let writer = std::thread::spawn(move || -> Result<()> {
+    ///     write_half.write_all(b"ping")?;
+    ///     write_half.flush()
+    /// });
+    ///
+    /// let mut buf = [0; 4];
+    /// peer.read_exact(&mut buf)?;
+    /// peer.write_all(b"pong")?;
+    /// peer.flush()?;
+    ///
+    /// read_half.read_exact(&mut buf)?;
+    /// assert_eq!(&buf, b"pong");
+    /// writer.join().unwrap()?;
+    /// # Ok(())}
+    /// ```
+    pub fn into_split(self) -> (OwnedReadHalf, OwnedWriteHalf) {
+        (
+            OwnedReadHalf {
+                reader: self.reader,
+            },
+            OwnedWriteHalf {
+                writer: self.writer,
+            },
+        )
+    }
+
+    pub(crate) fn outgoing_handle(&self) -> pipe::PipeHandle {
+        self.writer.outgoing.handle()
     }
 }
 
 impl Read for MemorySocket {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl Write for MemorySocket {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// The addresses associated with a connection, shared by both of its halves.
+#[derive(Default)]
+pub(crate) struct Endpoints {
+    pub(crate) local: Option<SocketAddr>,
+    pub(crate) peer: Option<SocketAddr>,
+    /// Held only to release the local port once both halves have been dropped
+    pub(crate) _port_reservation: Option<PortReservation>,
+}
+
+impl Endpoints {
+    fn local_addr(&self) -> Result<SocketAddr> {
+        self.local.ok_or_else(|| ErrorKind::AddrNotAvailable.into())
+    }
+
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        self.peer.ok_or_else(|| ErrorKind::AddrNotAvailable.into())
+    }
+}
+
+/// The reading half of a socket.
+pub(crate) struct Reader {
+    incoming: pipe::Receiver,
+    current_buffer: Option<Bytes>,
+    seen_eof: bool,
+    timeout: Option<Duration>,
+    nonblocking: bool,
+    endpoints: Arc<Endpoints>,
+    counters: Arc<Counters>,
+}
+
+impl Reader {
+    fn new(incoming: pipe::Receiver, endpoints: Arc<Endpoints>, counters: Arc<Counters>) -> Self {
+        Self {
+            incoming,
+            current_buffer: None,
+            seen_eof: false,
+            timeout: None,
+            nonblocking: false,
+            endpoints,
+            counters,
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let bytes_read = self.read_buffered(buf)?;
+        self.counters.record_read(bytes_read);
+        Ok(bytes_read)
+    }
+
+    fn read_buffered(&mut self, buf: &mut [u8]) -> Result<usize> {
         let mut bytes_read = 0;
 
         loop {
@@ -367,12 +1313,13 @@ impl Read for MemorySocket {
                         return Ok(bytes_read);
                     }
 
-                    self.current_buffer = match self.incoming.recv() {
-                        Ok(buf) => Some(buf),
+                    let deadline = deadline(self.nonblocking, self.timeout);
+                    self.current_buffer = match self.incoming.recv(deadline)? {
+                        Some(buf) => Some(buf),
 
                         // The remote side hung up, if this is the first time we've seen EOF then
                         // we should return `Ok(0)` otherwise an UnexpectedEof Error
-                        Err(_) => {
+                        None => {
                             if self.seen_eof {
                                 return Err(ErrorKind::UnexpectedEof.into());
                             } else {
@@ -387,19 +1334,130 @@ impl Read for MemorySocket {
     }
 }
 
-impl Write for MemorySocket {
+/// The writing half of a socket.
+pub(crate) struct Writer {
+    outgoing: pipe::Sender,
+    write_buffer: BytesMut,
+    policy: FlushPolicy,
+    discard_policy: DiscardPolicy,
+    timeout: Option<Duration>,
+    nonblocking: bool,
+    endpoints: Arc<Endpoints>,
+    counters: Arc<Counters>,
+    /// Whether the socket's randomness derives from the process seed, which is reported if the
+    /// socket is dropped while panicking
+    process_seeded: bool,
+}
+
+impl Writer {
+    fn new(outgoing: pipe::Sender, endpoints: Arc<Endpoints>, counters: Arc<Counters>) -> Self {
+        Self {
+            outgoing,
+            write_buffer: BytesMut::new(),
+            policy: FlushPolicy::default(),
+            discard_policy: DiscardPolicy::default(),
+            timeout: None,
+            nonblocking: false,
+            endpoints,
+            counters,
+            process_seeded: false,
+        }
+    }
+
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        self.write_buffer.extend_from_slice(buf);
+        self.outgoing.check()?;
+
+        // Avoid growing the write buffer without bound when the peer isn't keeping up
+        if let Some(capacity) = self.outgoing.capacity() {
+            if self.buffered() >= capacity {
+                self.flush()?;
+            }
+        }
+
+        self.buffer(buf)?;
+        self.counters.record_write(buf.len());
+        if self.should_flush() {
+            // The data has already been accepted, so if it can't be sent right away it stays
+            // buffered until the next flush
+            match self.flush() {
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                result => result?,
+            }
+        }
         Ok(buf.len())
     }
 
     fn flush(&mut self) -> Result<()> {
+        let deadline = deadline(self.nonblocking, self.timeout);
         if !self.write_buffer.is_empty() {
-            self.outgoing
-                .send(self.write_buffer.split().freeze())
-                .map_err(|_| ErrorKind::BrokenPipe.into())
-        } else {
-            Ok(())
+            self.outgoing.send(&mut self.write_buffer, deadline)?;
+        }
+        self.outgoing.send_staged(deadline)
+    }
+
+    /// Returns the number of bytes written but not yet sent.
+    fn buffered(&self) -> usize {
+        self.write_buffer.len() + self.outgoing.staged()
+    }
+
+    /// Hold on to `buf` until it is sent, as decided by the flush policy.
+    fn buffer(&mut self, buf: &[u8]) -> Result<()> {
+        match self.policy {
+            FlushPolicy::Nagle { delay } => self.outgoing.stage(buf, delay),
+            _ => {
+                self.write_buffer.extend_from_slice(buf);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns `true` if the flush policy calls for the buffered data to be sent.
+    fn should_flush(&self) -> bool {
+        match self.policy {
+            FlushPolicy::EveryWrite => true,
+            FlushPolicy::Threshold(threshold) => self.write_buffer.len() >= threshold,
+            FlushPolicy::Manual | FlushPolicy::Nagle { .. } => false,
         }
     }
+
+    /// Flush any buffered data and close the write side of the connection.
+    fn shutdown(&mut self) -> Result<()> {
+        if !self.outgoing.is_closed() {
+            self.flush()?;
+        }
+        self.outgoing.close();
+        Ok(())
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        // Deliver any buffered data before the peer observes EOF
+        let discarded = self.outgoing.finish(&mut self.write_buffer);
+        if discarded > 0 {
+            self.discard_policy
+                .report(discarded, self.endpoints.local_addr().ok());
+        }
+        if self.process_seeded {
+            network::report_process_seed();
+        }
+    }
+}
+
+/// The point in time at which a blocking operation limited by `timeout` should give up.
+fn deadline(nonblocking: bool, timeout: Option<Duration>) -> Option<Instant> {
+    if nonblocking {
+        Some(Instant::now())
+    } else {
+        timeout.map(|timeout| Instant::now() + timeout)
+    }
+}
+
+/// Reject zero durations the same way the standard library's socket timeouts do.
+fn check_timeout(timeout: Option<Duration>) -> Result<Option<Duration>> {
+    if timeout == Some(Duration::from_secs(0)) {
+        Err(ErrorKind::InvalidInput.into())
+    } else {
+        Ok(timeout)
+    }
 }
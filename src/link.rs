@@ -0,0 +1,110 @@
+use crate::rng::Rng;
+use std::{
+    cmp,
+    time::{Duration, Instant},
+};
+
+/// Conditions applied to the data flowing over an in-memory connection, used to mimic a real
+/// network link.
+///
+/// Every chunk of data flushed to the link is delivered to the peer after a fixed `latency`, plus
+/// a random delay chosen uniformly between zero and `jitter`. When a `bandwidth` is configured
+/// chunks also take time to be transmitted, queuing up behind one another. Data is always
+/// delivered in the order it was sent.
+///
+/// A link can be applied to a socket pair with [`PairBuilder::link`], to a single connection
+/// with [`MemorySocket::connect_with_link`] or to every connection made to a listener with
+/// [`MemoryListener::set_link`]. Each direction of a connection is conditioned independently.
+///
+/// [`MemoryListener::set_link`]: struct.MemoryListener.html#method.set_link
+/// [`MemorySocket::connect_with_link`]: struct.MemorySocket.html#method.connect_with_link
+/// [`PairBuilder::link`]: struct.PairBuilder.html#method.link
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_socket::{LinkConfig, MemorySocket};
+///
+/// let link = LinkConfig::new()
+///     .latency(Duration::from_millis(20))
+///     .jitter(Duration::from_millis(5))
+///     .bandwidth(1024 * 1024);
+/// let (socket_a, socket_b) = MemorySocket::builder().link(link).pair();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinkConfig {
+    latency: Duration,
+    jitter: Duration,
+    bandwidth: Option<u64>,
+}
+
+impl LinkConfig {
+    /// Create a new configuration for a link which delivers data immediately.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay the delivery of all data by a fixed `latency`.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Delay the delivery of each chunk of data by an additional random amount of up to `jitter`.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Limit the rate at which data is transmitted to `bytes_per_second`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_second` is `0`.
+    pub fn bandwidth(mut self, bytes_per_second: u64) -> Self {
+        assert!(bytes_per_second > 0, "bandwidth must be greater than zero");
+        self.bandwidth = Some(bytes_per_second);
+        self
+    }
+}
+
+/// The state of one direction of a conditioned connection.
+pub(crate) struct Link {
+    config: LinkConfig,
+    rng: Rng,
+    /// When the link will have finished transmitting the data already sent
+    idle_at: Option<Instant>,
+    /// When the most recently sent data will be delivered
+    last_delivery: Option<Instant>,
+}
+
+impl Link {
+    pub(crate) fn new(config: LinkConfig, rng: Rng) -> Self {
+        Self {
+            config,
+            rng,
+            idle_at: None,
+            last_delivery: None,
+        }
+    }
+
+    /// Returns when a chunk of `len` bytes, sent at `now`, will be delivered.
+    pub(crate) fn schedule(&mut self, len: usize, now: Instant) -> Instant {
+        let mut sent_at = now;
+        if let Some(bandwidth) = self.config.bandwidth {
+            let start = cmp::max(now, self.idle_at.unwrap_or(now));
+            let nanos = len as u128 * 1_000_000_000 / u128::from(bandwidth);
+            sent_at = start + Duration::from_nanos(nanos as u64);
+            self.idle_at = Some(sent_at);
+        }
+
+        let jitter = self.rng.up_to(self.config.jitter.as_nanos() as u64);
+        let deliver_at = sent_at + self.config.latency + Duration::from_nanos(jitter);
+
+        // Jitter must never reorder the bytes of a stream
+        let deliver_at = cmp::max(deliver_at, self.last_delivery.unwrap_or(deliver_at));
+        self.last_delivery = Some(deliver_at);
+        deliver_at
+    }
+}
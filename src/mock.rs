@@ -0,0 +1,315 @@
+//! A scripted peer for testing clients without writing a server.
+
+use crate::{FlushPolicy, MemorySocket};
+use std::{
+    fmt::Write as _,
+    io::{ErrorKind, Read, Write},
+    net::Shutdown,
+    panic,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How long the mock peer waits for each expected message by default.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A builder for a peer which follows a script, used to test a client against a server's
+/// expected behavior.
+///
+/// The script is a sequence of steps: expecting bytes from the client, responding with bytes,
+/// waiting, injecting an error or closing the connection. [`build`] returns the client's end of
+/// the connection along with a [`MockHandle`], while the peer follows the script on a background
+/// thread. If the client sends something other than what the script expects the peer panics with
+/// a description of the mismatch, and the panic is propagated to the test when the handle is
+/// joined or dropped.
+///
+/// The returned socket is an ordinary [`MemorySocket`], so it can be used with the synchronous
+/// as well as the asynchronous APIs.
+///
+/// [`build`]: #method.build
+/// [`MemorySocket`]: struct.MemorySocket.html
+/// [`MockHandle`]: struct.MockHandle.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use memory_socket::MockPeer;
+///
+/// # fn main () -> ::std::io::Result<()> {
+/// let (mut socket, mock) = MockPeer::new()
+///     .expect(b"ping")
+///     .respond(b"pong")
+///     .close()
+///     .build();
+///
+/// socket.write_all(b"ping")?;
+/// socket.flush()?;
+///
+/// let mut response = Vec::new();
+/// socket.read_to_end(&mut response)?;
+/// assert_eq!(response, b"pong");
+///
+/// mock.join();
+/// # Ok(())}
+/// ```
+pub struct MockPeer {
+    steps: Vec<Step>,
+    timeout: Duration,
+}
+
+/// A check applied to the bytes received for an expectation.
+type Predicate = Box<dyn Fn(&[u8]) -> bool + Send>;
+
+enum Step {
+    Expect(Vec<u8>),
+    ExpectWith { len: usize, predicate: Predicate },
+    Respond(Vec<u8>),
+    Wait(Duration),
+    Error(ErrorKind),
+    Close,
+}
+
+impl MockPeer {
+    /// Create a new, empty script.
+    pub fn new() -> Self {
+        Self {
+            steps: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Expect the client to send exactly `bytes`.
+    pub fn expect(mut self, bytes: &[u8]) -> Self {
+        self.steps.push(Step::Expect(bytes.to_vec()));
+        self
+    }
+
+    /// Expect the client to send `len` bytes which satisfy `predicate`.
+    pub fn expect_with<F>(mut self, len: usize, predicate: F) -> Self
+    where
+        F: Fn(&[u8]) -> bool + Send + 'static,
+    {
+        self.steps.push(Step::ExpectWith {
+            len,
+            predicate: Box::new(predicate),
+        });
+        self
+    }
+
+    /// Send `bytes` to the client.
+    pub fn respond(mut self, bytes: &[u8]) -> Self {
+        self.steps.push(Step::Respond(bytes.to_vec()));
+        self
+    }
+
+    /// Pause for `duration` before carrying on with the script.
+    pub fn wait(mut self, duration: Duration) -> Self {
+        self.steps.push(Step::Wait(duration));
+        self
+    }
+
+    /// Cause the client's next read to fail with an error of the given `kind`, once it has read
+    /// everything sent before it.
+    pub fn error(mut self, kind: ErrorKind) -> Self {
+        self.steps.push(Step::Error(kind));
+        self
+    }
+
+    /// Shut down the peer's side of the connection, so that the client observes EOF once it has
+    /// read everything sent before it. The peer can still expect data afterwards.
+    pub fn close(mut self) -> Self {
+        self.steps.push(Step::Close);
+        self
+    }
+
+    /// Set how long the peer waits for each expected message before panicking. Defaults to five
+    /// seconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `timeout` is zero.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        assert!(timeout > Duration::from_secs(0), "timeout must be non-zero");
+        self.timeout = timeout;
+        self
+    }
+
+    /// Start following the script, returning the client's end of the connection and a handle to
+    /// the peer.
+    pub fn build(self) -> (MemorySocket, MockHandle) {
+        let (socket, mut peer) = MemorySocket::new_pair();
+        peer.set_flush_policy(FlushPolicy::EveryWrite);
+        peer.set_read_timeout(Some(self.timeout))
+            .expect("timeout is non-zero");
+
+        let thread = thread::Builder::new()
+            .name("memory-socket-mock".to_owned())
+            .spawn(move || run(self.steps, peer))
+            .expect("failed to spawn the mock peer");
+
+        (
+            socket,
+            MockHandle {
+                thread: Some(thread),
+            },
+        )
+    }
+}
+
+impl Default for MockPeer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Follow `steps` on the `peer`'s end of the connection.
+fn run(steps: Vec<Step>, mut peer: MemorySocket) {
+    for (index, step) in steps.into_iter().enumerate() {
+        let step_number = index + 1;
+        match step {
+            Step::Expect(expected) => {
+                let actual = receive(&mut peer, expected.len(), step_number);
+                if actual != expected {
+                    panic!(
+                        "mock peer received unexpected data at step {}\n{}",
+                        step_number,
+                        diff(&expected, &actual)
+                    );
+                }
+            }
+            Step::ExpectWith { len, predicate } => {
+                let actual = receive(&mut peer, len, step_number);
+                if !predicate(&actual) {
+                    panic!(
+                        "mock peer received data which didn't match the predicate at step {}\n  \
+                         received: {}",
+                        step_number,
+                        escape(&actual)
+                    );
+                }
+            }
+            Step::Respond(bytes) => {
+                // The client hanging up is its own business, any missed expectations will be
+                // reported by later steps
+                let _ = peer.write_all(&bytes);
+            }
+            Step::Wait(duration) => thread::sleep(duration),
+            Step::Error(kind) => peer.writer.outgoing.inject_error(kind),
+            Step::Close => {
+                let _ = peer.shutdown(Shutdown::Write);
+            }
+        }
+    }
+
+    // Anything else the client has sent by now wasn't in the script
+    let _ = peer.set_nonblocking(true);
+    let mut extra = Vec::new();
+    let mut buf = [0; 1024];
+    while let Ok(len) = peer.read(&mut buf) {
+        if len == 0 {
+            break;
+        }
+        extra.extend_from_slice(&buf[..len]);
+    }
+    if !extra.is_empty() {
+        panic!(
+            "mock peer received unexpected data after the end of the script\n  received: {}",
+            escape(&extra)
+        );
+    }
+}
+
+/// Read exactly `len` bytes from the client, panicking if they don't arrive.
+fn receive(peer: &mut MemorySocket, len: usize, step_number: usize) -> Vec<u8> {
+    let mut actual = vec![0; len];
+    let mut filled = 0;
+    while filled < len {
+        match peer.read(&mut actual[filled..]) {
+            Ok(0) => panic!(
+                "mock peer expected {} bytes at step {} but the client closed the connection\n  \
+                 received: {}",
+                len,
+                step_number,
+                escape(&actual[..filled])
+            ),
+            Ok(n) => filled += n,
+            Err(error) if error.kind() == ErrorKind::WouldBlock => panic!(
+                "mock peer timed out waiting for {} bytes at step {}\n  received: {}",
+                len,
+                step_number,
+                escape(&actual[..filled])
+            ),
+            Err(error) => panic!(
+                "mock peer failed to read at step {}: {}\n  received: {}",
+                step_number,
+                error,
+                escape(&actual[..filled])
+            ),
+        }
+    }
+    actual
+}
+
+/// Describe how `actual` differs from `expected`.
+fn diff(expected: &[u8], actual: &[u8]) -> String {
+    let offset = expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+
+    let mut diff = String::new();
+    let _ = writeln!(diff, "  expected: {}", escape(expected));
+    let _ = writeln!(diff, "  received: {}", escape(actual));
+    let _ = write!(diff, "  first difference at byte {}", offset);
+    diff
+}
+
+/// Render `bytes` as an escaped byte string literal.
+pub(crate) fn escape(bytes: &[u8]) -> String {
+    let escaped = bytes
+        .iter()
+        .flat_map(|&byte| std::ascii::escape_default(byte))
+        .map(char::from)
+        .collect::<String>();
+    format!("b\"{}\"", escaped)
+}
+
+/// A handle to a running [`MockPeer`].
+///
+/// Joining or dropping the handle waits for the peer to finish its script, propagating any panic
+/// caused by the script not being followed. When dropped while the thread is already panicking
+/// the peer's panic is discarded.
+///
+/// [`MockPeer`]: struct.MockPeer.html
+pub struct MockHandle {
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockHandle {
+    /// Wait for the peer to finish its script.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the client didn't follow the script.
+    pub fn join(mut self) {
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            if let Err(payload) = thread.join() {
+                if !thread::panicking() {
+                    panic::resume_unwind(payload);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for MockHandle {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
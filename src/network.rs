@@ -0,0 +1,1336 @@
+use crate::{
+    backlog::Backlog,
+    capture::{Capture, Direction, Flow, Tap},
+    check_timeout,
+    datagram::Datagram,
+    partition::Partition,
+    pipe::PipeHandle,
+    resolve::{self, Host},
+    rng::Rng,
+    Endpoints, LinkConfig, MemoryDatagramSocket, MemoryListener, MemorySocket, PairBuilder,
+    PartitionId, PartitionMode, SegmentConfig, ToMemoryAddrs,
+};
+use flume::Sender;
+use once_cell::sync::Lazy;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufWriter, Error, ErrorKind, Result, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+/// The range of ports from which the local port of an outgoing connection is chosen.
+const EPHEMERAL_PORTS: (u16, u16) = (49152, 65535);
+
+/// The environment variable from which [`MemoryNetwork::simulation`] and the process seed are
+/// read.
+///
+/// [`MemoryNetwork::simulation`]: struct.MemoryNetwork.html#method.simulation
+const SEED_VAR: &str = "MEMORY_SOCKET_SEED";
+
+/// The seed of the randomness used outside of explicitly seeded networks and socket pairs, read
+/// from `MEMORY_SOCKET_SEED` or chosen at random once per process.
+static PROCESS_SEED: Lazy<u64> =
+    Lazy::new(|| seed_from_env().unwrap_or_else(|| Rng::from_entropy().next_u64()));
+
+/// Hands out the seeds of socket pairs built without one, derived from the process seed.
+static PROCESS_RNG: Lazy<Mutex<Rng>> = Lazy::new(|| Mutex::new(Rng::new(*PROCESS_SEED)));
+
+/// The network used by [`MemoryListener::bind`] and [`MemorySocket::connect`], seeded with the
+/// process seed.
+///
+/// [`MemoryListener::bind`]: struct.MemoryListener.html#method.bind
+/// [`MemorySocket::connect`]: struct.MemorySocket.html#method.connect
+static DEFAULT_NETWORK: Lazy<MemoryNetwork> = Lazy::new(|| {
+    let network = MemoryNetwork::with_seed(*PROCESS_SEED);
+    // The default network is never dropped, so its sockets report the seed instead
+    network.inner.switchboard().process_seeded = true;
+    network
+});
+
+/// Read a seed from `MEMORY_SOCKET_SEED`, panicking if it is set but invalid.
+fn seed_from_env() -> Option<u64> {
+    let seed = std::env::var(SEED_VAR).ok()?;
+    Some(seed.trim().parse().unwrap_or_else(|_| {
+        panic!(
+            "{} must be an unsigned 64-bit integer, got {:?}",
+            SEED_VAR, seed
+        )
+    }))
+}
+
+/// A seed for a socket pair built without one, derived from the process seed.
+pub(crate) fn next_process_seed() -> u64 {
+    PROCESS_RNG.lock().unwrap().next_u64()
+}
+
+/// Print the process seed if the thread is panicking, at most once per process.
+pub(crate) fn report_process_seed() {
+    static REPORTED: AtomicBool = AtomicBool::new(false);
+    if std::thread::panicking() && !REPORTED.swap(true, Ordering::Relaxed) {
+        print_seed(*PROCESS_SEED);
+    }
+}
+
+fn print_seed(seed: u64) {
+    eprintln!(
+        "memory-socket: simulation seed was {0}, replay with {1}={0}",
+        seed, SEED_VAR
+    );
+}
+
+/// An isolated in-memory network namespace.
+///
+/// Every `MemoryNetwork` owns its own set of bound addresses, so listeners and sockets created in
+/// one network can never observe those created in another. This makes it possible to run many
+/// independent tests in parallel without them fighting over the same addresses.
+///
+/// The free-standing [`MemoryListener::bind`] and [`MemorySocket::connect`] functions operate on
+/// a process-wide [default network], which is seeded from the `MEMORY_SOCKET_SEED` environment
+/// variable when it is set.
+///
+/// When a `MemoryNetwork` is dropped every listener and socket created in it is torn down:
+/// listeners stop accepting connections and sockets, including those whose connections were
+/// never accepted, observe EOF when reading and a broken pipe when writing.
+///
+/// A network created with [`with_seed`] or [`simulation`] runs as a deterministic simulation,
+/// where all of the randomness the crate introduces is derived from a single seed so that a
+/// failing test can be replayed exactly.
+///
+/// [default network]: #method.default_network
+/// [`with_seed`]: #method.with_seed
+/// [`simulation`]: #method.simulation
+/// [`MemoryListener::bind`]: struct.MemoryListener.html#method.bind
+/// [`MemorySocket::connect`]: struct.MemorySocket.html#method.connect
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Read, Result, Write};
+/// use memory_socket::MemoryNetwork;
+///
+/// # fn main() -> Result<()> {
+/// let network = MemoryNetwork::new();
+/// let listener = network.bind("192.51.100.2:1337".parse().unwrap())?;
+///
+/// let mut dialer = network.connect("192.51.100.2:1337".parse().unwrap())?;
+/// let mut listener_socket = listener.accept()?;
+///
+/// dialer.write_all(b"stormlight")?;
+/// dialer.flush()?;
+///
+/// let mut buf = [0; 10];
+/// listener_socket.read_exact(&mut buf)?;
+/// assert_eq!(&buf, b"stormlight");
+/// # Ok(())}
+/// ```
+pub struct MemoryNetwork {
+    inner: Arc<Network>,
+}
+
+impl MemoryNetwork {
+    /// Create a new, empty network namespace.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// let network = MemoryNetwork::new();
+    /// ```
+    pub fn new() -> Self {
+        Self::with_rng(None, Rng::from_entropy(), 1, EPHEMERAL_PORTS.0)
+    }
+
+    /// Create a new network namespace running as a deterministic simulation driven by `seed`.
+    ///
+    /// Every source of randomness the crate introduces within the network is derived from the
+    /// seed: the ports assigned when binding to port `0` and to outgoing connections, the jitter
+    /// of links, the [segmentation] of data and the datagrams dropped due to [loss]. Creating
+    /// another network with the same seed and driving it with the same sequence of operations
+    /// reproduces the same behavior.
+    ///
+    /// If the network is dropped while the thread is panicking, such as when a test fails, the
+    /// seed is printed to stderr so that the failure can be replayed with
+    /// [`simulation`](#method.simulation).
+    ///
+    /// [loss]: #method.set_datagram_loss
+    /// [segmentation]: #method.set_segmentation
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let a = MemoryNetwork::with_seed(7);
+    /// let b = MemoryNetwork::with_seed(7);
+    ///
+    /// let listener_a = a.bind("192.51.100.2:0".parse().unwrap())?;
+    /// let listener_b = b.bind("192.51.100.2:0".parse().unwrap())?;
+    /// assert_eq!(listener_a.local_addr(), listener_b.local_addr());
+    /// # Ok(())}
+    /// ```
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        // Start from random ports, below the ephemeral range for listeners and within it for
+        // outgoing connections
+        let next_port = 1024 + rng.up_to(u64::from(EPHEMERAL_PORTS.0 - 1025)) as u16;
+        let next_ephemeral_port =
+            EPHEMERAL_PORTS.0 + rng.up_to(u64::from(EPHEMERAL_PORTS.1 - EPHEMERAL_PORTS.0)) as u16;
+        Self::with_rng(Some(seed), rng, next_port, next_ephemeral_port)
+    }
+
+    /// Create a new network namespace running as a deterministic simulation, seeded from the
+    /// `MEMORY_SOCKET_SEED` environment variable if it is set or from entropy otherwise.
+    ///
+    /// This is the recommended way of creating a simulated network in tests: a fresh seed is
+    /// used on each run, and a failure can be replayed by setting `MEMORY_SOCKET_SEED` to the
+    /// seed printed when the test panicked. See [`with_seed`] for details.
+    ///
+    /// [`with_seed`]: #method.with_seed
+    ///
+    /// # Panics
+    ///
+    /// Panics if `MEMORY_SOCKET_SEED` is set but isn't an unsigned 64-bit integer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// let network = MemoryNetwork::simulation();
+    /// println!("running with seed {}", network.seed().unwrap());
+    /// ```
+    pub fn simulation() -> Self {
+        let seed = seed_from_env().unwrap_or_else(|| Rng::from_entropy().next_u64());
+        Self::with_seed(seed)
+    }
+
+    fn with_rng(seed: Option<u64>, rng: Rng, next_port: u16, next_ephemeral_port: u16) -> Self {
+        Self {
+            inner: Arc::new(Network {
+                switchboard: Mutex::new(SwitchBoard {
+                    listeners: HashMap::default(),
+                    datagrams: HashMap::default(),
+                    links: HashMap::default(),
+                    next_port,
+                    next_ephemeral_port,
+                    connection_ports: HashSet::new(),
+                    connections: Vec::new(),
+                    partitions: HashMap::default(),
+                    hosts: HashMap::default(),
+                    capture: None,
+                    seed,
+                    process_seeded: false,
+                    rng,
+                    datagram_loss: 0.0,
+                    segmentation: None,
+                    next_partition_id: 0,
+                }),
+                partitions_changed: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Returns the process-wide network used by the free-standing functions such as
+    /// [`MemoryListener::bind`] and [`MemorySocket::connect`].
+    ///
+    /// The default network runs as a simulation seeded with the process seed, which is read from
+    /// the `MEMORY_SOCKET_SEED` environment variable if it is set or chosen at random otherwise.
+    /// The same seed drives socket pairs built without a [seed], such as those returned by
+    /// [`MemorySocket::new_pair`]. Since the default network is never dropped, the process seed is
+    /// instead printed when one of its listeners or sockets, or an unseeded pair, is dropped while
+    /// the thread is panicking.
+    ///
+    /// [seed]: struct.PairBuilder.html#method.seed
+    /// [`MemorySocket::new_pair`]: struct.MemorySocket.html#method.new_pair
+    /// [`MemoryListener::bind`]: struct.MemoryListener.html#method.bind
+    /// [`MemorySocket::connect`]: struct.MemorySocket.html#method.connect
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{MemoryNetwork, MemorySocket};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// MemoryNetwork::default_network().register_host("cache.internal", "192.51.100.8".parse().unwrap());
+    /// # let _listener = memory_socket::MemoryListener::bind("192.51.100.8:6379".parse().unwrap())?;
+    /// let socket = MemorySocket::connect_to("cache.internal:6379")?;
+    /// # Ok(())}
+    /// ```
+    pub fn default_network() -> &'static Self {
+        &DEFAULT_NETWORK
+    }
+
+    /// Add `ip` to the addresses which the host `name` resolves to.
+    ///
+    /// A name can have any number of IPv4 and IPv6 addresses registered, which are returned in
+    /// the order they were added. Names are case insensitive. Registering an address for a name
+    /// which was set to [fail] clears the failure.
+    ///
+    /// [fail]: #method.fail_host
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// network.register_host("db.internal", "192.51.100.5".parse().unwrap());
+    /// network.register_host("db.internal", "2001:db8::5".parse().unwrap());
+    ///
+    /// assert_eq!(network.lookup_host("DB.internal")?.len(), 2);
+    /// # Ok(())}
+    /// ```
+    pub fn register_host(&self, name: &str, ip: IpAddr) {
+        let mut switchboard = self.inner.switchboard();
+        let host = switchboard
+            .hosts
+            .entry(resolve::normalize(name))
+            .or_insert_with(|| Host::Records(Vec::new()));
+        match host {
+            Host::Records(records) if !records.contains(&ip) => records.push(ip),
+            Host::Records(_) => {}
+            Host::Failure(_) => *host = Host::Records(vec![ip]),
+        }
+    }
+
+    /// Remove every address registered for the host `name`, along with any configured failure.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// let network = MemoryNetwork::new();
+    /// network.register_host("db.internal", "192.51.100.5".parse().unwrap());
+    /// network.deregister_host("db.internal");
+    ///
+    /// assert!(network.lookup_host("db.internal").is_err());
+    /// ```
+    pub fn deregister_host(&self, name: &str) {
+        self.inner
+            .switchboard()
+            .hosts
+            .remove(&resolve::normalize(name));
+    }
+
+    /// Make resolving the host `name` fail with an error of the given kind, replacing any
+    /// addresses registered for it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::ErrorKind;
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// let network = MemoryNetwork::new();
+    /// network.fail_host("flaky.internal", ErrorKind::TimedOut);
+    ///
+    /// let result = network.lookup_host("flaky.internal");
+    /// assert_eq!(result.unwrap_err().kind(), ErrorKind::TimedOut);
+    /// ```
+    pub fn fail_host(&self, name: &str, kind: ErrorKind) {
+        self.inner
+            .switchboard()
+            .hosts
+            .insert(resolve::normalize(name), Host::Failure(kind));
+    }
+
+    /// Resolve `host` to the IP addresses registered for it.
+    ///
+    /// IP literals resolve to themselves. An error of kind `NotFound` is returned if no addresses
+    /// are registered for the name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// assert_eq!(
+    ///     network.lookup_host("192.51.100.5")?,
+    ///     vec!["192.51.100.5".parse::<std::net::IpAddr>().unwrap()]
+    /// );
+    /// # Ok(())}
+    /// ```
+    pub fn lookup_host(&self, host: &str) -> Result<Vec<IpAddr>> {
+        // Allow IPv6 literals to be given in their bracketed form
+        let literal = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = literal.parse() {
+            return Ok(vec![ip]);
+        }
+
+        match self
+            .inner
+            .switchboard()
+            .hosts
+            .get(&resolve::normalize(host))
+        {
+            Some(Host::Records(records)) if !records.is_empty() => Ok(records.clone()),
+            Some(Host::Failure(kind)) => Err(Error::new(*kind, "failed to resolve host")),
+            _ => Err(Error::new(ErrorKind::NotFound, "host not found")),
+        }
+    }
+
+    /// Creates a new `MemoryListener` in this network bound to the first address `address`
+    /// resolves to which can be bound.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// network.register_host("db.internal", "192.51.100.5".parse().unwrap());
+    /// let listener = network.bind_to("db.internal:5432")?;
+    /// # Ok(())}
+    /// ```
+    pub fn bind_to<A: ToMemoryAddrs>(&self, address: A) -> Result<MemoryListener> {
+        self.each_addr(address, |address| self.bind(address))
+    }
+
+    /// Create a new in-memory Socket in this network connected to `address`, trying each of
+    /// the addresses it resolves to in turn until one succeeds.
+    ///
+    /// If every attempt fails, the error from the last one is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// network.register_host("db.internal", "192.51.100.5".parse().unwrap());
+    /// # let _listener = network.bind("192.51.100.5:5432".parse().unwrap())?;
+    /// let socket = network.connect_to("db.internal:5432")?;
+    /// # Ok(())}
+    /// ```
+    pub fn connect_to<A: ToMemoryAddrs>(&self, address: A) -> Result<MemorySocket> {
+        self.each_addr(address, |address| self.connect(address))
+    }
+
+    fn each_addr<A: ToMemoryAddrs, T>(
+        &self,
+        address: A,
+        mut f: impl FnMut(SocketAddr) -> Result<T>,
+    ) -> Result<T> {
+        let mut last_error = None;
+        for address in address.to_memory_addrs(self)? {
+            match f(address) {
+                Ok(value) => return Ok(value),
+                Err(error) => last_error = Some(error),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        }))
+    }
+
+    /// Creates a new `MemoryListener` in this network which will be bound to the specified
+    /// address.
+    ///
+    /// See [`MemoryListener::bind`] for more details.
+    ///
+    /// [`MemoryListener::bind`]: struct.MemoryListener.html#method.bind
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// let listener = network.bind("192.51.100.2:1337".parse().unwrap())?;
+    /// # Ok(())}
+    /// ```
+    pub fn bind(&self, address: SocketAddr) -> Result<MemoryListener> {
+        self.bind_listener(address, None)
+    }
+
+    /// Bind a new listener whose queue of connections waiting to be accepted is limited to
+    /// `backlog`, if given.
+    pub(crate) fn bind_listener(
+        &self,
+        mut address: SocketAddr,
+        backlog: Option<usize>,
+    ) -> Result<MemoryListener> {
+        let mut switchboard = self.inner.switchboard();
+
+        // If they didn't provide a port find one that isn't in use.
+        if address.port() == 0 {
+            address = switchboard.free_port(address, SwitchBoard::is_in_use)?;
+        } else if switchboard.is_in_use(&address) {
+            // Can't listen on the same address and port twice
+            return Err(ErrorKind::AddrInUse.into());
+        }
+
+        let backlog = Arc::new(Backlog::new(backlog));
+        switchboard.listeners.insert(address, backlog.clone());
+
+        Ok(MemoryListener::new(
+            backlog,
+            address,
+            Arc::downgrade(&self.inner),
+        ))
+    }
+
+    /// Creates a new `MemoryDatagramSocket` in this network which will be bound to the specified
+    /// address.
+    ///
+    /// Datagram sockets are bound independently of listeners, so both can share the same address.
+    /// See [`MemoryDatagramSocket::bind`] for more details.
+    ///
+    /// [`MemoryDatagramSocket::bind`]: struct.MemoryDatagramSocket.html#method.bind
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// let _listener = network.bind("192.51.100.2:1337".parse().unwrap())?;
+    /// let _socket = network.bind_datagram("192.51.100.2:1337".parse().unwrap())?;
+    /// # Ok(())}
+    /// ```
+    pub fn bind_datagram(&self, mut address: SocketAddr) -> Result<MemoryDatagramSocket> {
+        let mut switchboard = self.inner.switchboard();
+
+        if address.port() == 0 {
+            address = switchboard.free_port(address, |switchboard, address| {
+                switchboard.datagrams.contains_key(address)
+            })?;
+        } else if switchboard.datagrams.contains_key(&address) {
+            return Err(ErrorKind::AddrInUse.into());
+        }
+
+        let (sender, receiver) = flume::unbounded();
+        switchboard.datagrams.insert(address, sender);
+
+        Ok(MemoryDatagramSocket::new(
+            receiver,
+            address,
+            Arc::downgrade(&self.inner),
+        ))
+    }
+
+    /// Create a new in-memory Socket in this network connected to the specified address.
+    ///
+    /// See [`MemorySocket::connect`] for more details.
+    ///
+    /// [`MemorySocket::connect`]: struct.MemorySocket.html#method.connect
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// # let _listener = network.bind("192.51.100.2:60".parse().unwrap())?;
+    /// let socket = network.connect("192.51.100.2:60".parse().unwrap())?;
+    /// # Ok(())}
+    /// ```
+    pub fn connect(&self, address: SocketAddr) -> Result<MemorySocket> {
+        self.connect_from(SocketAddr::new(address.ip(), 0), address)
+    }
+
+    /// Create a new in-memory Socket in this network connected to the specified address,
+    /// originating from `local_address`.
+    ///
+    /// See [`MemorySocket::connect_from`] for more details.
+    ///
+    /// [`MemorySocket::connect_from`]: struct.MemorySocket.html#method.connect_from
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// # let _listener = network.bind("192.51.100.2:60".parse().unwrap())?;
+    /// let socket = network.connect_from(
+    ///     "192.51.100.7:0".parse().unwrap(),
+    ///     "192.51.100.2:60".parse().unwrap(),
+    /// )?;
+    /// # Ok(())}
+    /// ```
+    pub fn connect_from(
+        &self,
+        local_address: SocketAddr,
+        address: SocketAddr,
+    ) -> Result<MemorySocket> {
+        self.connect_inner(local_address, address, None, None)
+    }
+
+    /// Create a new in-memory Socket in this network connected to the specified address,
+    /// waiting up to `timeout` for room in the listener's backlog.
+    ///
+    /// See [`MemorySocket::connect_timeout`] for more details.
+    ///
+    /// [`MemorySocket::connect_timeout`]: struct.MemorySocket.html#method.connect_timeout
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// # let _listener = network.bind("192.51.100.2:60".parse().unwrap())?;
+    /// let socket = network.connect_timeout(
+    ///     "192.51.100.2:60".parse().unwrap(),
+    ///     Duration::from_millis(100),
+    /// )?;
+    /// # Ok(())}
+    /// ```
+    pub fn connect_timeout(&self, address: SocketAddr, timeout: Duration) -> Result<MemorySocket> {
+        let timeout = check_timeout(Some(timeout))?;
+        self.connect_inner(SocketAddr::new(address.ip(), 0), address, None, timeout)
+    }
+
+    /// Create a new in-memory Socket in this network connected to the specified address, with
+    /// the data flowing in each direction conditioned by `link`.
+    ///
+    /// See [`MemorySocket::connect_with_link`] for more details.
+    ///
+    /// [`MemorySocket::connect_with_link`]: struct.MemorySocket.html#method.connect_with_link
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_socket::{LinkConfig, MemoryNetwork};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// # let _listener = network.bind("192.51.100.2:60".parse().unwrap())?;
+    /// let link = LinkConfig::new().latency(Duration::from_millis(10));
+    /// let socket = network.connect_with_link("192.51.100.2:60".parse().unwrap(), link)?;
+    /// # Ok(())}
+    /// ```
+    pub fn connect_with_link(&self, address: SocketAddr, link: LinkConfig) -> Result<MemorySocket> {
+        self.connect_inner(SocketAddr::new(address.ip(), 0), address, Some(link), None)
+    }
+
+    fn connect_inner(
+        &self,
+        mut local_address: SocketAddr,
+        address: SocketAddr,
+        link: Option<LinkConfig>,
+        timeout: Option<Duration>,
+    ) -> Result<MemorySocket> {
+        // Nothing can ever listen on these, so there is no route to them
+        if address.ip().is_unspecified() || address.port() == 0 {
+            return Err(ErrorKind::AddrNotAvailable.into());
+        }
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut switchboard = self.inner.switchboard();
+        if local_address.ip().is_unspecified() {
+            local_address.set_ip(address.ip());
+        }
+
+        // Across a stalled partition the connection attempt goes unanswered, so it only succeeds
+        // if the partition heals before the attempt times out
+        loop {
+            match switchboard.partition_mode(local_address.ip(), address.ip()) {
+                Some(PartitionMode::Stall) => {
+                    let deadline = deadline.ok_or(ErrorKind::TimedOut)?;
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(ErrorKind::TimedOut.into());
+                    }
+                    switchboard = self
+                        .inner
+                        .partitions_changed
+                        .wait_timeout(switchboard, deadline - now)
+                        .unwrap()
+                        .0;
+                }
+                Some(PartitionMode::Reset) => return Err(ErrorKind::ConnectionRefused.into()),
+                None => break,
+            }
+        }
+
+        let listener = SwitchBoard::route(&switchboard.listeners, address)
+            .ok_or(ErrorKind::ConnectionRefused)?;
+        if local_address.port() == 0 {
+            local_address = switchboard.ephemeral_port(local_address)?;
+        } else if switchboard.is_in_use(&local_address) {
+            return Err(ErrorKind::AddrInUse.into());
+        }
+
+        // A link given for this connection takes precedence over the listener's
+        let mut builder = PairBuilder::new().seed(switchboard.rng.next_u64());
+        if let Some(segmentation) = switchboard.segmentation.clone() {
+            builder = builder.segmentation(segmentation);
+        }
+        if let Some(link) = link.or_else(|| switchboard.links.get(&listener).cloned()) {
+            builder = builder.link(link);
+        }
+        let (mut socket_a, mut socket_b) = builder.pair();
+        if switchboard.process_seeded {
+            socket_a.set_process_seeded();
+            socket_b.set_process_seeded();
+        }
+        let handles = (socket_a.outgoing_handle(), socket_b.outgoing_handle());
+        let flow = switchboard.capture.as_ref().map(|capture| {
+            let flow = Flow::open(capture.clone(), local_address, address);
+            handles
+                .0
+                .set_tap(Tap::new(flow.clone(), Direction::ToClient));
+            handles
+                .1
+                .set_tap(Tap::new(flow.clone(), Direction::ToServer));
+            flow
+        });
+        socket_a.set_endpoints(Endpoints {
+            local: Some(address),
+            peer: Some(local_address),
+            _port_reservation: None,
+        });
+
+        let backlog = switchboard.listeners[&listener].clone();
+        switchboard.connection_ports.insert(local_address);
+        switchboard.track(Connection {
+            endpoints: (local_address, address),
+            listener,
+            pipes: handles,
+        });
+        socket_b.set_endpoints(Endpoints {
+            local: Some(local_address),
+            peer: Some(address),
+            _port_reservation: Some(PortReservation {
+                network: Arc::downgrade(&self.inner),
+                address: local_address,
+            }),
+        });
+
+        // Hand the socket to the listener, without holding up the rest of the network while
+        // waiting for room in its backlog. If the listener has gone away in the meantime the
+        // connection is refused. The handshake is only completed in the capture once the
+        // connection has been queued, before the listener can accept it and send anything.
+        drop(switchboard);
+        let pushed = backlog.push(socket_a, deadline, || {
+            if let Some(flow) = &flow {
+                flow.accept();
+            }
+        });
+        if let Err(error) = pushed {
+            if let Some(flow) = &flow {
+                flow.refuse();
+            }
+            return Err(error);
+        }
+
+        Ok(socket_b)
+    }
+}
+
+impl MemoryNetwork {
+    /// Partition the IP addresses in `a` from those in `b`, simulating a split in the network.
+    ///
+    /// New connections across the partition fail and existing connections either stall or are
+    /// reset, depending on `mode`. With [`PartitionMode::Stall`] any data sent while the
+    /// partition is in place is held back and delivered once it is [healed]. Datagrams sent
+    /// across the partition are dropped.
+    ///
+    /// [healed]: #method.heal
+    /// [`PartitionMode::Stall`]: enum.PartitionMode.html#variant.Stall
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::ErrorKind;
+    /// use memory_socket::{MemoryNetwork, PartitionMode};
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// let _listener = network.bind("192.51.100.2:60".parse().unwrap())?;
+    ///
+    /// let partition = network.partition(
+    ///     vec!["192.51.100.2".parse().unwrap()],
+    ///     vec!["192.51.100.3".parse().unwrap()],
+    ///     PartitionMode::Reset,
+    /// );
+    /// let result = network.connect_from(
+    ///     "192.51.100.3:0".parse().unwrap(),
+    ///     "192.51.100.2:60".parse().unwrap(),
+    /// );
+    /// assert_eq!(result.err().map(|e| e.kind()), Some(ErrorKind::ConnectionRefused));
+    ///
+    /// network.heal(partition);
+    /// # Ok(())}
+    /// ```
+    pub fn partition<A, B>(&self, a: A, b: B, mode: PartitionMode) -> PartitionId
+    where
+        A: IntoIterator<Item = IpAddr>,
+        B: IntoIterator<Item = IpAddr>,
+    {
+        let mut switchboard = self.inner.switchboard();
+        let id = PartitionId(switchboard.next_partition_id);
+        switchboard.next_partition_id += 1;
+        switchboard.partitions.insert(
+            id,
+            Partition {
+                a: a.into_iter().collect(),
+                b: b.into_iter().collect(),
+                mode,
+            },
+        );
+        switchboard.apply_partitions();
+        self.inner.partitions_changed.notify_all();
+        id
+    }
+
+    /// Remove a partition created by [`partition`], resuming the delivery of any data held back
+    /// by it.
+    ///
+    /// [`partition`]: #method.partition
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{MemoryNetwork, PartitionMode};
+    ///
+    /// let network = MemoryNetwork::new();
+    /// let partition = network.partition(
+    ///     vec!["192.51.100.2".parse().unwrap()],
+    ///     vec!["192.51.100.3".parse().unwrap()],
+    ///     PartitionMode::Stall,
+    /// );
+    /// network.heal(partition);
+    /// ```
+    pub fn heal(&self, partition: PartitionId) {
+        let mut switchboard = self.inner.switchboard();
+        if switchboard.partitions.remove(&partition).is_some() {
+            switchboard.apply_partitions();
+            self.inner.partitions_changed.notify_all();
+        }
+    }
+
+    /// Reset every open connection with an endpoint at `address`, returning the number of
+    /// connections reset.
+    ///
+    /// This simulates the abrupt death of a peer: both sides of each connection have any data in
+    /// flight discarded, and their reads and writes fail with an error of kind `ConnectionReset`.
+    /// Resetting the address of a listener resets every connection accepted by it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{ErrorKind, Read};
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// let listener = network.bind("192.51.100.2:60".parse().unwrap())?;
+    /// let mut socket = network.connect(listener.local_addr())?;
+    ///
+    /// assert_eq!(network.reset_connections(listener.local_addr()), 1);
+    ///
+    /// let mut buf = [0; 4];
+    /// assert_eq!(socket.read(&mut buf).unwrap_err().kind(), ErrorKind::ConnectionReset);
+    /// # Ok(())}
+    /// ```
+    pub fn reset_connections(&self, address: SocketAddr) -> usize {
+        let mut switchboard = self.inner.switchboard();
+        switchboard.connections.retain(Connection::is_alive);
+
+        let mut reset = 0;
+        for connection in &switchboard.connections {
+            let (x, y) = connection.endpoints;
+            if x == address || y == address || connection.listener == address {
+                connection.reset();
+                reset += 1;
+            }
+        }
+        reset
+    }
+
+    /// Remove every partition in this network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// let network = MemoryNetwork::new();
+    /// network.heal_all();
+    /// ```
+    pub fn heal_all(&self) {
+        let mut switchboard = self.inner.switchboard();
+        switchboard.partitions.clear();
+        switchboard.apply_partitions();
+        self.inner.partitions_changed.notify_all();
+    }
+}
+
+impl MemoryNetwork {
+    /// Returns the seed driving this network, if it is running as a deterministic
+    /// [simulation](#method.with_seed).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// assert_eq!(MemoryNetwork::with_seed(7).seed(), Some(7));
+    /// assert_eq!(MemoryNetwork::new().seed(), None);
+    /// ```
+    pub fn seed(&self) -> Option<u64> {
+        self.inner.switchboard().seed
+    }
+
+    /// Drop each datagram sent within this network with the given `probability`, which must be
+    /// between `0.0` and `1.0`.
+    ///
+    /// Which datagrams are dropped is decided by the network's seed when running as a
+    /// [simulation](#method.with_seed).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `probability` is outside of the range `0.0..=1.0`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// let network = MemoryNetwork::with_seed(7);
+    /// network.set_datagram_loss(0.1);
+    /// ```
+    pub fn set_datagram_loss(&self, probability: f64) {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "probability must be between 0.0 and 1.0"
+        );
+        self.inner.switchboard().datagram_loss = probability;
+    }
+
+    /// Apply `segmentation` to every new connection made within this network, breaking up the
+    /// data flushed to them before it is read. Existing connections are not affected.
+    ///
+    /// See [`SegmentConfig`] for more details.
+    ///
+    /// [`SegmentConfig`]: struct.SegmentConfig.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::{MemoryNetwork, SegmentConfig};
+    ///
+    /// let network = MemoryNetwork::with_seed(7);
+    /// network.set_segmentation(Some(SegmentConfig::new().mtu(64).random(true)));
+    /// ```
+    pub fn set_segmentation(&self, segmentation: Option<SegmentConfig>) {
+        self.inner.switchboard().segmentation = segmentation;
+    }
+}
+
+impl MemoryNetwork {
+    /// Start recording the traffic of new connections in this network to `writer`, in the
+    /// pcapng format.
+    ///
+    /// Each flushed chunk of data is recorded as a packet with synthesized IP and TCP headers,
+    /// so the capture can be opened in tools such as Wireshark and the payload decoded by their
+    /// standard dissectors. Connections are recorded with a handshake when they are established
+    /// and a FIN or RST when they are shut down or reset. Only connections established after
+    /// the capture is started are recorded, and datagrams are not recorded.
+    ///
+    /// Any capture already in progress is stopped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pcapng headers couldn't be written to `writer`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// network.capture(std::io::sink())?;
+    ///
+    /// let _listener = network.bind("192.51.100.2:60".parse().unwrap())?;
+    /// let mut socket = network.connect("192.51.100.2:60".parse().unwrap())?;
+    /// socket.write_all(b"hello")?;
+    /// socket.flush()?;
+    ///
+    /// network.stop_capture()?;
+    /// # Ok(())}
+    /// ```
+    pub fn capture<W: Write + Send + 'static>(&self, writer: W) -> Result<()> {
+        let capture = Arc::new(Capture::new(Box::new(writer))?);
+        let previous = self.inner.switchboard().capture.replace(capture);
+        match previous {
+            Some(previous) => previous.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Start recording the traffic of new connections in this network to a pcapng file at
+    /// `path`, creating or truncating it.
+    ///
+    /// See [`capture`] for details.
+    ///
+    /// [`capture`]: #method.capture
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// network.capture_to_file("traffic.pcapng")?;
+    /// # Ok(())}
+    /// ```
+    pub fn capture_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.capture(BufWriter::new(File::create(path)?))
+    }
+
+    /// Stop recording traffic, flushing the capture.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error encountered while writing the capture, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemoryNetwork;
+    ///
+    /// # fn main () -> ::std::io::Result<()> {
+    /// let network = MemoryNetwork::new();
+    /// network.capture(Vec::new())?;
+    /// network.stop_capture()?;
+    /// # Ok(())}
+    /// ```
+    pub fn stop_capture(&self) -> Result<()> {
+        let capture = self.inner.switchboard().capture.take();
+        match capture {
+            Some(capture) => capture.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for MemoryNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for MemoryNetwork {
+    fn drop(&mut self) {
+        let mut switchboard = self.inner.switchboard();
+
+        if let (Some(seed), true) = (switchboard.seed, std::thread::panicking()) {
+            print_seed(seed);
+        }
+
+        // Shutting down each listener's backlog wakes up any pending accepts and
+        // closes queued connections, and closing the pipes tears down open connections.
+        for (_, backlog) in switchboard.listeners.drain() {
+            backlog.shut_down();
+        }
+        switchboard.datagrams.clear();
+        for connection in switchboard.connections.drain(..) {
+            connection.pipes.0.close();
+            connection.pipes.1.close();
+        }
+        if let Some(capture) = switchboard.capture.take() {
+            let _ = capture.finish();
+        }
+    }
+}
+
+/// State shared between a [`MemoryNetwork`] and the listeners bound within it.
+///
+/// [`MemoryNetwork`]: struct.MemoryNetwork.html
+pub(crate) struct Network {
+    switchboard: Mutex<SwitchBoard>,
+    /// Signalled whenever partitions are added or removed
+    partitions_changed: Condvar,
+}
+
+impl Network {
+    fn switchboard(&self) -> MutexGuard<'_, SwitchBoard> {
+        self.switchboard.lock().unwrap()
+    }
+
+    /// Print the process seed if the thread is panicking and the network is seeded with it.
+    pub(crate) fn report_seed(network: &Weak<Self>) {
+        if !std::thread::panicking() {
+            return;
+        }
+        if let Some(network) = network.upgrade() {
+            if network.switchboard().process_seeded {
+                report_process_seed();
+            }
+        }
+    }
+
+    /// Close `backlog`, removing it from `address` if it is still the listener bound there.
+    pub(crate) fn close_listener(
+        network: &Weak<Self>,
+        address: &SocketAddr,
+        backlog: &Arc<Backlog>,
+    ) {
+        if let Some(network) = network.upgrade() {
+            let mut switchboard = network.switchboard();
+            if switchboard.is_listener(address, backlog) {
+                switchboard.listeners.remove(address);
+                switchboard.links.remove(address);
+            }
+        }
+        backlog.close();
+    }
+
+    /// Condition new connections made to the listener bound to `address` with `link`.
+    pub(crate) fn set_link(
+        network: &Weak<Self>,
+        address: SocketAddr,
+        backlog: &Arc<Backlog>,
+        link: Option<LinkConfig>,
+    ) {
+        if let Some(network) = network.upgrade() {
+            let mut switchboard = network.switchboard();
+            if !switchboard.is_listener(&address, backlog) {
+                return;
+            }
+            match link {
+                Some(link) => switchboard.links.insert(address, link),
+                None => switchboard.links.remove(&address),
+            };
+        }
+    }
+
+    /// Remove the datagram socket bound to `address`.
+    pub(crate) fn unbind_datagram(network: &Weak<Self>, address: &SocketAddr) {
+        if let Some(network) = network.upgrade() {
+            network.switchboard().datagrams.remove(address);
+        }
+    }
+
+    /// Deliver `datagram` to the socket bound to its destination, dropping it if there is none.
+    pub(crate) fn send_datagram(network: &Weak<Self>, mut datagram: Datagram) {
+        let address = datagram.1;
+        // Like a connection, a datagram from a wildcard address which doesn't already know which
+        // IP to use is sent from the destination's IP
+        if datagram.0.ip().is_unspecified() {
+            datagram.0.set_ip(address.ip());
+        }
+        if let Some(network) = network.upgrade() {
+            let mut switchboard = network.switchboard();
+            if switchboard
+                .partition_mode(datagram.0.ip(), address.ip())
+                .is_some()
+            {
+                return;
+            }
+            let loss = switchboard.datagram_loss;
+            if loss > 0.0 && switchboard.rng.chance(loss) {
+                return;
+            }
+            if let Some(socket) = SwitchBoard::route(&switchboard.datagrams, address) {
+                let _ = switchboard.datagrams[&socket].send(datagram);
+            }
+        }
+    }
+}
+
+/// Keeps the local port of an outgoing connection reserved for as long as the connection is open.
+pub(crate) struct PortReservation {
+    network: Weak<Network>,
+    address: SocketAddr,
+}
+
+impl Drop for PortReservation {
+    fn drop(&mut self) {
+        if let Some(network) = self.network.upgrade() {
+            network.switchboard().connection_ports.remove(&self.address);
+        }
+    }
+}
+
+/// Collection of bound listeners and open connections
+struct SwitchBoard {
+    listeners: HashMap<SocketAddr, Arc<Backlog>>,
+    /// Bound datagram sockets, kept apart from stream listeners
+    datagrams: HashMap<SocketAddr, Sender<Datagram>>,
+    /// Link conditions applied to new connections to a listener
+    links: HashMap<SocketAddr, LinkConfig>,
+    next_port: u16,
+    next_ephemeral_port: u16,
+    /// Local addresses of outgoing connections
+    connection_ports: HashSet<SocketAddr>,
+    connections: Vec<Connection>,
+    partitions: HashMap<PartitionId, Partition>,
+    next_partition_id: u64,
+    /// Registry of host names
+    hosts: HashMap<String, Host>,
+    /// Where the traffic of new connections is recorded, if anywhere
+    capture: Option<Arc<Capture>>,
+    /// The seed of a simulated network
+    seed: Option<u64>,
+    /// Whether the network is seeded with the process seed, which its sockets and listeners
+    /// report in place of the network
+    process_seeded: bool,
+    /// The source of all randomness within the network
+    rng: Rng,
+    /// Probability of a datagram being dropped
+    datagram_loss: f64,
+    /// Segmentation applied to new connections
+    segmentation: Option<SegmentConfig>,
+}
+
+/// An open connection between two addresses.
+struct Connection {
+    endpoints: (SocketAddr, SocketAddr),
+    /// The address the accepting listener is bound to, which may be unspecified
+    listener: SocketAddr,
+    pipes: (PipeHandle, PipeHandle),
+}
+
+impl Connection {
+    fn is_alive(&self) -> bool {
+        self.pipes.0.is_alive() || self.pipes.1.is_alive()
+    }
+
+    fn reset(&self) {
+        self.pipes.0.reset();
+        self.pipes.1.reset();
+    }
+}
+
+impl SwitchBoard {
+    /// Returns `true` if `backlog` belongs to the listener bound to `address`, which it may not
+    /// once that listener has been closed.
+    fn is_listener(&self, address: &SocketAddr, backlog: &Arc<Backlog>) -> bool {
+        matches!(self.listeners.get(address), Some(bound) if Arc::ptr_eq(bound, backlog))
+    }
+
+    /// Find the address of the socket in `table` which traffic to `address` should be delivered
+    /// to.
+    ///
+    /// As with the OS, a socket bound to exactly `address` takes precedence over one bound to the
+    /// unspecified IP of the same family, which in turn takes precedence over a dual-stack socket
+    /// bound to `[::]`.
+    fn route<T>(table: &HashMap<SocketAddr, T>, address: SocketAddr) -> Option<SocketAddr> {
+        let port = address.port();
+        let candidates = [
+            address,
+            match address {
+                SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port),
+                SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), port),
+            },
+            SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), port),
+        ];
+
+        candidates
+            .iter()
+            .find(|candidate| table.contains_key(candidate))
+            .copied()
+    }
+
+    fn is_in_use(&self, address: &SocketAddr) -> bool {
+        self.listeners.contains_key(address) || self.connection_ports.contains(address)
+    }
+
+    /// Find a port on the IP of `address`, which `in_use` reports isn't already taken.
+    fn free_port(
+        &mut self,
+        mut address: SocketAddr,
+        in_use: impl Fn(&Self, &SocketAddr) -> bool,
+    ) -> Result<SocketAddr> {
+        let start_port = self.next_port;
+        address.set_port(self.next_port);
+        while in_use(self, &address) {
+            self.next_port += 1;
+            if self.next_port == u16::MAX {
+                self.next_port = 1;
+            }
+            if self.next_port == start_port {
+                return Err(ErrorKind::AddrInUse.into());
+            }
+            address.set_port(self.next_port);
+        }
+        Ok(address)
+    }
+
+    /// Find an ephemeral port on the IP of `address` which isn't already in use.
+    fn ephemeral_port(&mut self, mut address: SocketAddr) -> Result<SocketAddr> {
+        let start_port = self.next_ephemeral_port;
+        loop {
+            address.set_port(self.next_ephemeral_port);
+            self.next_ephemeral_port = if self.next_ephemeral_port == EPHEMERAL_PORTS.1 {
+                EPHEMERAL_PORTS.0
+            } else {
+                self.next_ephemeral_port + 1
+            };
+
+            if !self.is_in_use(&address) {
+                return Ok(address);
+            }
+            if self.next_ephemeral_port == start_port {
+                return Err(ErrorKind::AddrInUse.into());
+            }
+        }
+    }
+
+    /// Keep track of the pipes backing a connection so that they can be torn down along with the
+    /// network.
+    fn track(&mut self, connection: Connection) {
+        self.connections.retain(Connection::is_alive);
+        self.connections.push(connection);
+    }
+
+    /// Returns how traffic between `x` and `y` is affected by partitions, if at all. Resetting
+    /// takes precedence over stalling.
+    fn partition_mode(&self, x: IpAddr, y: IpAddr) -> Option<PartitionMode> {
+        self.partitions
+            .values()
+            .filter(|partition| partition.separates(x, y))
+            .map(|partition| partition.mode)
+            .max_by_key(|mode| *mode == PartitionMode::Reset)
+    }
+
+    /// Stall or reset open connections according to the current set of partitions.
+    fn apply_partitions(&mut self) {
+        self.connections.retain(Connection::is_alive);
+        for connection in &self.connections {
+            let (a, b) = &connection.pipes;
+            let (x, y) = connection.endpoints;
+            match self.partition_mode(x.ip(), y.ip()) {
+                Some(PartitionMode::Reset) => connection.reset(),
+                mode => {
+                    let held = mode == Some(PartitionMode::Stall);
+                    a.set_held(held);
+                    b.set_held(held);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::{collections::HashSet, net::IpAddr};
+
+/// How connections across a network partition behave, see [`MemoryNetwork::partition`].
+///
+/// [`MemoryNetwork::partition`]: struct.MemoryNetwork.html#method.partition
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PartitionMode {
+    /// Data sent over existing connections is held back until the partition is healed, and new
+    /// connections fail with an error of kind `TimedOut`. A connection made with a timeout waits
+    /// for the partition to heal, failing only once the timeout has elapsed.
+    Stall,
+    /// Existing connections are reset, and new connections fail with an error of kind
+    /// `ConnectionRefused`.
+    Reset,
+}
+
+/// Identifies a partition created by [`MemoryNetwork::partition`], so that it can later be
+/// [healed].
+///
+/// [healed]: struct.MemoryNetwork.html#method.heal
+/// [`MemoryNetwork::partition`]: struct.MemoryNetwork.html#method.partition
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PartitionId(pub(crate) u64);
+
+/// Two sets of IP addresses which are unable to reach one another.
+pub(crate) struct Partition {
+    pub(crate) a: HashSet<IpAddr>,
+    pub(crate) b: HashSet<IpAddr>,
+    pub(crate) mode: PartitionMode,
+}
+
+impl Partition {
+    /// Returns `true` if traffic between `x` and `y` crosses this partition.
+    pub(crate) fn separates(&self, x: IpAddr, y: IpAddr) -> bool {
+        (self.a.contains(&x) && self.b.contains(&y)) || (self.a.contains(&y) && self.b.contains(&x))
+    }
+}
@@ -0,0 +1,699 @@
+//! A unidirectional in-memory byte pipe.
+//!
+//! Each direction of a [`MemorySocket`] is backed by a pipe. Chunks of bytes are pushed in by the
+//! [`Sender`] when the writing side flushes and are handed out, in order, by the [`Receiver`].
+//!
+//! A pipe can optionally be given a capacity, bounding the number of bytes which can be in flight
+//! at any one time. Once the capacity is reached the writing side must wait for the reading side
+//! to catch up.
+//!
+//! A pipe can also be given a [`Link`], which delays the delivery of each chunk to mimic a real
+//! network link.
+//!
+//! Data can also be staged on a pipe rather than sent right away, coalescing small writes in the
+//! manner of Nagle's algorithm. Staged data is sent once the data already in flight has been
+//! read, once enough of it accumulates or once it has been staged for long enough.
+//!
+//! A pipe can be given a [`Segmenter`] too, which breaks up and merges chunks before they are
+//! read.
+//!
+//! The traffic on a pipe can be recorded by attaching a [`Tap`].
+//!
+//! [`Link`]: ../link/struct.Link.html
+//! [`Tap`]: ../capture/struct.Tap.html
+//! [`MemorySocket`]: ../struct.MemorySocket.html
+//! [`Segmenter`]: ../segment/struct.Segmenter.html
+
+use crate::{capture::Tap, link::Link, segment::Segmenter};
+use bytes::{Bytes, BytesMut};
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind, Result},
+    sync::{Arc, Condvar, Mutex, MutexGuard, Weak},
+    task::Waker,
+    time::{Duration, Instant},
+};
+
+#[cfg(any(feature = "async", feature = "tokio"))]
+use std::task::{Context, Poll};
+
+/// Construct both ends of a new pipe, optionally bounding the number of bytes in flight,
+/// conditioning the delivery of data with a `link` and segmenting it with a `segmenter`.
+pub(crate) fn pipe(
+    capacity: Option<usize>,
+    link: Option<Link>,
+    segmenter: Option<Segmenter>,
+) -> (Sender, Receiver) {
+    let pipe = Arc::new(Pipe {
+        state: Mutex::new(State {
+            chunks: VecDeque::new(),
+            capacity,
+            link,
+            segmenter,
+            staged: BytesMut::new(),
+            staged_until: None,
+            queued: 0,
+            pushed: 0,
+            popped: 0,
+            injected: None,
+            max_queued: 0,
+            chunks_sent: 0,
+            write_closed: false,
+            read_closed: false,
+            held: false,
+            reset: false,
+            tap: None,
+            read_waker: None,
+            write_waker: None,
+            #[cfg(any(feature = "async", feature = "tokio"))]
+            read_timer: None,
+        }),
+        readable: Condvar::new(),
+        writable: Condvar::new(),
+    });
+
+    (Sender { pipe: pipe.clone() }, Receiver { pipe })
+}
+
+struct Pipe {
+    state: Mutex<State>,
+    readable: Condvar,
+    writable: Condvar,
+}
+
+struct State {
+    chunks: VecDeque<Chunk>,
+    capacity: Option<usize>,
+    link: Option<Link>,
+    segmenter: Option<Segmenter>,
+    /// Data written but held back from being sent, to be coalesced with later writes
+    staged: BytesMut,
+    /// When the staged data is sent regardless of the data still in flight
+    staged_until: Option<Instant>,
+    /// Number of bytes currently held in `chunks`
+    queued: usize,
+    /// Total number of bytes pushed onto and popped off of the pipe
+    pushed: u64,
+    popped: u64,
+    /// An error to be returned to the reading side once it has read the given number of bytes
+    injected: Option<(u64, ErrorKind)>,
+    /// The largest number of bytes which have been held in `chunks` at once
+    max_queued: usize,
+    /// Number of chunks which have been pushed onto the pipe
+    chunks_sent: u64,
+    write_closed: bool,
+    read_closed: bool,
+    /// Data is being held back from the reading side, as though the link were down
+    held: bool,
+    /// The connection has been reset, failing all further operations on either side
+    reset: bool,
+    /// Records the data sent on the pipe and how it is torn down
+    tap: Option<Tap>,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+    /// The deadline and waker of the timer last registered to wake the reading side once delayed
+    /// data arrives
+    #[cfg(any(feature = "async", feature = "tokio"))]
+    read_timer: Option<(Instant, Waker)>,
+}
+
+struct Chunk {
+    bytes: Bytes,
+    /// When the chunk becomes visible to the reading side, if delayed by a link
+    deliver_at: Option<Instant>,
+}
+
+/// Staged data is sent as soon as this much of it accumulates, the maximum segment size of a
+/// loopback interface.
+const STAGED_SEGMENT: usize = 65483;
+
+/// The outcome of trying to take the next chunk off of a pipe.
+enum Delivery {
+    Ready(Bytes),
+    /// The next chunk is still in transit and will arrive at the given time
+    Delayed(Instant),
+    /// Nothing can be delivered until the pipe is released
+    Held,
+    Empty,
+}
+
+impl State {
+    /// Push as much of `buf` onto the pipe as its capacity allows, returning `true` once all of
+    /// it has been sent.
+    fn push(&mut self, buf: &mut BytesMut, sent_at: Instant) -> Result<bool> {
+        self.check_writable()?;
+
+        let available = match self.capacity {
+            Some(capacity) => capacity.saturating_sub(self.queued),
+            None => buf.len(),
+        };
+        let len = ::std::cmp::min(available, buf.len());
+        if len > 0 {
+            self.queued += len;
+            self.pushed += len as u64;
+            self.max_queued = ::std::cmp::max(self.max_queued, self.queued);
+            self.chunks_sent += 1;
+            let deliver_at = self.link.as_mut().map(|link| link.schedule(len, sent_at));
+            let bytes = buf.split_to(len).freeze();
+            let segments = match &mut self.segmenter {
+                Some(segmenter) => segmenter.split(bytes),
+                None => vec![bytes],
+            };
+            for bytes in segments {
+                if let Some(tap) = &self.tap {
+                    tap.data(&bytes);
+                }
+                self.chunks.push_back(Chunk { bytes, deliver_at });
+            }
+        }
+
+        Ok(buf.is_empty())
+    }
+
+    /// Send as much of the staged data as the pipe's capacity allows, or all of it if `force`d,
+    /// as though it were sent at `sent_at`.
+    fn send_staged(&mut self, force: bool, sent_at: Instant) -> Result<bool> {
+        if self.staged.is_empty() {
+            return Ok(true);
+        }
+        let capacity = self.capacity;
+        if force {
+            self.capacity = None;
+        }
+        let mut staged = std::mem::take(&mut self.staged);
+        let result = self.push(&mut staged, sent_at);
+        self.staged = staged;
+        self.capacity = capacity;
+
+        if self.staged.is_empty() {
+            self.staged_until = None;
+        }
+        result
+    }
+
+    /// Returns an error if no more data can be sent on the pipe.
+    fn check_writable(&self) -> Result<()> {
+        if self.reset {
+            Err(ErrorKind::ConnectionReset.into())
+        } else if self.read_closed || self.write_closed {
+            Err(ErrorKind::BrokenPipe.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Close the writing side of the pipe, sending any staged data first.
+    fn close_write(&mut self) {
+        if !self.staged.is_empty() && self.check_writable().is_ok() {
+            let _ = self.send_staged(true, Instant::now());
+        }
+        if !self.write_closed {
+            self.write_closed = true;
+            if let Some(tap) = &self.tap {
+                tap.fin();
+            }
+        }
+    }
+
+    /// Make sure the reading task is woken by `deliver_at`, registering a timer unless one which
+    /// fires early enough is already pending for the same task.
+    #[cfg(any(feature = "async", feature = "tokio"))]
+    fn schedule_read_timer(&mut self, deliver_at: Instant, waker: &Waker) {
+        if let Some((at, registered)) = &self.read_timer {
+            if *at > Instant::now() && *at <= deliver_at && registered.will_wake(waker) {
+                return;
+            }
+        }
+        crate::timer::wake_at(deliver_at, waker.clone());
+        self.read_timer = Some((deliver_at, waker.clone()));
+    }
+
+    fn pop(&mut self) -> Result<Delivery> {
+        if self.reset {
+            return Err(ErrorKind::ConnectionReset.into());
+        }
+        // Nothing more will be read once the reading side has closed, so a stalled link mustn't
+        // keep the reader waiting
+        if self.read_closed {
+            return Ok(Delivery::Empty);
+        }
+        if self.held {
+            return Ok(Delivery::Held);
+        }
+        if let Some((at, kind)) = self.injected {
+            if self.popped >= at {
+                self.injected = None;
+                return Err(kind.into());
+            }
+        }
+        if !self.staged.is_empty() {
+            let now = Instant::now();
+            match self.staged_until {
+                Some(staged_until) if staged_until > now => {
+                    if self.chunks.is_empty() {
+                        return Ok(Delivery::Delayed(staged_until));
+                    }
+                }
+                // The staged data has been held back for long enough, so it is sent as of when
+                // its delay ran out
+                staged_until => {
+                    self.send_staged(false, staged_until.unwrap_or(now))?;
+                }
+            }
+        }
+        match self.chunks.front().and_then(|chunk| chunk.deliver_at) {
+            Some(deliver_at) if deliver_at > Instant::now() => {
+                return Ok(Delivery::Delayed(deliver_at))
+            }
+            _ => {}
+        }
+
+        Ok(match self.chunks.pop_front() {
+            Some(chunk) => {
+                self.queued -= chunk.bytes.len();
+                self.popped += chunk.bytes.len() as u64;
+                let bytes = self.coalesce(chunk.bytes);
+                // Everything in flight has been read, so staged data can be sent right away
+                if self.queued == 0 && !self.staged.is_empty() {
+                    self.send_staged(false, Instant::now())?;
+                }
+                Delivery::Ready(bytes)
+            }
+            None => Delivery::Empty,
+        })
+    }
+
+    /// Merge `bytes` with any following chunks which have also arrived, as decided by the
+    /// segmenter.
+    fn coalesce(&mut self, bytes: Bytes) -> Bytes {
+        let segmenter = match &mut self.segmenter {
+            Some(segmenter) => segmenter,
+            None => return bytes,
+        };
+
+        let now = Instant::now();
+        let mut merged: Option<BytesMut> = None;
+        while let Some(next) = self.chunks.front() {
+            let arrived = match next.deliver_at {
+                Some(deliver_at) => deliver_at <= now,
+                None => true,
+            };
+            let len = merged.as_ref().map_or(bytes.len(), BytesMut::len);
+            // Don't merge past the point at which an error is to be returned
+            let interrupted = match self.injected {
+                Some((at, _)) => self.popped >= at,
+                None => false,
+            };
+            if !arrived || interrupted || !segmenter.coalesce(len, next.bytes.len()) {
+                break;
+            }
+
+            let next = self.chunks.pop_front().unwrap();
+            self.queued -= next.bytes.len();
+            self.popped += next.bytes.len() as u64;
+            merged
+                .get_or_insert_with(|| BytesMut::from(&bytes[..]))
+                .extend_from_slice(&next.bytes);
+        }
+
+        match merged {
+            Some(merged) => merged.freeze(),
+            None => bytes,
+        }
+    }
+}
+
+impl Pipe {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Block on `condvar` until notified or until `deadline` has passed, in which case an error of
+    /// kind `WouldBlock` is returned.
+    fn wait<'a>(
+        &self,
+        condvar: &Condvar,
+        state: MutexGuard<'a, State>,
+        deadline: Option<Instant>,
+    ) -> Result<MutexGuard<'a, State>> {
+        match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(ErrorKind::WouldBlock.into());
+                }
+                Ok(condvar.wait_timeout(state, deadline - now).unwrap().0)
+            }
+            None => Ok(condvar.wait(state).unwrap()),
+        }
+    }
+
+    fn notify_readable(&self, state: &mut State) {
+        self.readable.notify_all();
+        if let Some(waker) = state.read_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn notify_writable(&self, state: &mut State) {
+        self.writable.notify_all();
+        if let Some(waker) = state.write_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Close both ends of the pipe, discarding any data in flight and waking up anyone waiting on
+    /// it.
+    fn close(&self) {
+        let mut state = self.lock();
+        state.close_write();
+        state.read_closed = true;
+        self.discard(&mut state);
+    }
+
+    /// Reset the pipe, discarding any data in flight and causing both sides to observe a
+    /// connection reset.
+    fn reset(&self) {
+        let mut state = self.lock();
+        state.reset = true;
+        if let Some(tap) = &state.tap {
+            tap.reset();
+        }
+        self.discard(&mut state);
+    }
+
+    /// Discard any data in flight, waking up anyone waiting on the pipe.
+    fn discard(&self, state: &mut State) {
+        state.chunks.clear();
+        state.staged.clear();
+        state.staged_until = None;
+        state.queued = 0;
+        self.notify_readable(state);
+        self.notify_writable(state);
+    }
+}
+
+/// A weak reference to a pipe which can be used to tear it down from outside of the socket which
+/// owns it.
+pub(crate) struct PipeHandle(Weak<Pipe>);
+
+impl PipeHandle {
+    /// Returns `false` once both ends of the pipe have been dropped.
+    pub(crate) fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+
+    /// Close the pipe, causing the reading side to observe EOF and the writing side to observe a
+    /// broken pipe.
+    pub(crate) fn close(&self) {
+        if let Some(pipe) = self.0.upgrade() {
+            pipe.close();
+        }
+    }
+
+    /// Reset the pipe, discarding any data in flight and causing both sides to observe a
+    /// connection reset.
+    pub(crate) fn reset(&self) {
+        if let Some(pipe) = self.0.upgrade() {
+            pipe.reset();
+        }
+    }
+
+    /// Record the traffic on the pipe with `tap`.
+    pub(crate) fn set_tap(&self, tap: Tap) {
+        if let Some(pipe) = self.0.upgrade() {
+            pipe.lock().tap = Some(tap);
+        }
+    }
+
+    /// Hold back data sent on the pipe from the reading side, or release it.
+    pub(crate) fn set_held(&self, held: bool) {
+        if let Some(pipe) = self.0.upgrade() {
+            let mut state = pipe.lock();
+            if state.held != held {
+                state.held = held;
+                pipe.notify_readable(&mut state);
+            }
+        }
+    }
+}
+
+/// The writing end of a pipe.
+pub(crate) struct Sender {
+    pipe: Arc<Pipe>,
+}
+
+impl Sender {
+    pub(crate) fn handle(&self) -> PipeHandle {
+        PipeHandle(Arc::downgrade(&self.pipe))
+    }
+
+    /// Returns the maximum number of bytes which can be in flight, if bounded.
+    pub(crate) fn capacity(&self) -> Option<usize> {
+        self.pipe.lock().capacity
+    }
+
+    /// Returns the number of chunks sent on the pipe and the largest number of bytes which have
+    /// been in flight at once.
+    pub(crate) fn stats(&self) -> (u64, usize) {
+        let state = self.pipe.lock();
+        (state.chunks_sent, state.max_queued)
+    }
+
+    /// Returns `true` if no more data can be sent on the pipe.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.check().is_err()
+    }
+
+    /// Returns an error of kind `BrokenPipe` if the pipe has been closed, or `ConnectionReset` if
+    /// it has been reset.
+    pub(crate) fn check(&self) -> Result<()> {
+        self.pipe.lock().check_writable()
+    }
+
+    /// Close the writing side of the pipe. The reading side will observe EOF once it has consumed
+    /// all of the data already in flight.
+    pub(crate) fn close(&self) {
+        let mut state = self.pipe.lock();
+        state.close_write();
+        self.pipe.notify_readable(&mut state);
+    }
+
+    /// Cause the reading side to observe an error of the given `kind` once it has read all of the
+    /// data sent so far, including any staged data.
+    pub(crate) fn inject_error(&self, kind: ErrorKind) {
+        let mut state = self.pipe.lock();
+        if !state.staged.is_empty() && state.check_writable().is_ok() {
+            let _ = state.send_staged(true, Instant::now());
+        }
+        state.injected = Some((state.pushed, kind));
+        self.pipe.notify_readable(&mut state);
+    }
+
+    /// Send `buf` followed by any staged data, regardless of the pipe's capacity, and then close
+    /// the writing side of the pipe.
+    ///
+    /// Returns the number of bytes which were discarded because the pipe could no longer be
+    /// written to.
+    pub(crate) fn finish(&self, buf: &mut BytesMut) -> usize {
+        let mut state = self.pipe.lock();
+        let pending = buf.len() + state.staged.len();
+        if pending > 0 && state.check_writable().is_err() {
+            buf.clear();
+            state.staged.clear();
+            return pending;
+        }
+
+        if !buf.is_empty() {
+            let capacity = state.capacity.take();
+            let _ = state.push(buf, Instant::now());
+            state.capacity = capacity;
+        }
+        state.close_write();
+        self.pipe.notify_readable(&mut state);
+        0
+    }
+
+    /// Reset the pipe, causing both sides to observe a connection reset.
+    pub(crate) fn reset(&self) {
+        self.pipe.reset();
+    }
+
+    /// Returns the number of bytes which have been staged but not yet sent.
+    pub(crate) fn staged(&self) -> usize {
+        self.pipe.lock().staged.len()
+    }
+
+    /// Stage `buf` to be sent along with later writes, in the manner of Nagle's algorithm.
+    ///
+    /// The data is sent right away if nothing else is in flight or once a full segment has been
+    /// staged. Otherwise it is held back until the data in flight has been read or `delay` has
+    /// passed, whichever comes first.
+    pub(crate) fn stage(&self, buf: &[u8], delay: Duration) -> Result<()> {
+        let mut state = self.pipe.lock();
+        state.check_writable()?;
+        state.staged.extend_from_slice(buf);
+
+        if state.queued == 0 || state.staged.len() >= STAGED_SEGMENT {
+            state.send_staged(false, Instant::now())?;
+        }
+        if !state.staged.is_empty() && state.staged_until.is_none() {
+            state.staged_until = Some(Instant::now() + delay);
+        }
+        self.pipe.notify_readable(&mut state);
+        Ok(())
+    }
+
+    /// Send all of the staged data, blocking while the pipe is at capacity.
+    ///
+    /// If `deadline` passes before all of the data could be sent an error of kind `WouldBlock` is
+    /// returned, leaving the remaining data staged.
+    pub(crate) fn send_staged(&self, deadline: Option<Instant>) -> Result<()> {
+        let mut state = self.pipe.lock();
+        loop {
+            let done = state.send_staged(false, Instant::now())?;
+            self.pipe.notify_readable(&mut state);
+            if done {
+                return Ok(());
+            }
+            state = self.pipe.wait(&self.pipe.writable, state, deadline)?;
+        }
+    }
+
+    /// Send all of the staged data, registering the current task to be woken if the pipe is at
+    /// capacity.
+    #[cfg(any(feature = "async", feature = "tokio"))]
+    pub(crate) fn poll_send_staged(&self, context: &mut Context) -> Poll<Result<()>> {
+        let mut state = self.pipe.lock();
+        let done = state.send_staged(false, Instant::now())?;
+        self.pipe.notify_readable(&mut state);
+        if done {
+            Poll::Ready(Ok(()))
+        } else {
+            state.write_waker = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    /// Move the contents of `buf` onto the pipe, blocking while the pipe is at capacity.
+    ///
+    /// If `deadline` passes before all of `buf` could be sent an error of kind `WouldBlock` is
+    /// returned, leaving the remaining data in `buf`.
+    pub(crate) fn send(&self, buf: &mut BytesMut, deadline: Option<Instant>) -> Result<()> {
+        let mut state = self.pipe.lock();
+        loop {
+            let done = state.push(buf, Instant::now())?;
+            self.pipe.notify_readable(&mut state);
+            if done {
+                return Ok(());
+            }
+            state = self.pipe.wait(&self.pipe.writable, state, deadline)?;
+        }
+    }
+
+    /// Move the contents of `buf` onto the pipe, registering the current task to be woken if the
+    /// pipe is at capacity.
+    #[cfg(any(feature = "async", feature = "tokio"))]
+    pub(crate) fn poll_send(&self, context: &mut Context, buf: &mut BytesMut) -> Poll<Result<()>> {
+        let mut state = self.pipe.lock();
+        let done = state.push(buf, Instant::now())?;
+        self.pipe.notify_readable(&mut state);
+        if done {
+            Poll::Ready(Ok(()))
+        } else {
+            state.write_waker = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// The reading end of a pipe.
+pub(crate) struct Receiver {
+    pipe: Arc<Pipe>,
+}
+
+impl Receiver {
+    /// Close the reading side of the pipe, discarding any data in flight. The writing side will
+    /// observe a broken pipe on its next send.
+    pub(crate) fn close(&self) {
+        let mut state = self.pipe.lock();
+        state.read_closed = true;
+        state.chunks.clear();
+        state.staged.clear();
+        state.staged_until = None;
+        state.queued = 0;
+        self.pipe.notify_writable(&mut state);
+    }
+
+    /// Reset the pipe, causing both sides to observe a connection reset.
+    pub(crate) fn reset(&self) {
+        self.pipe.reset();
+    }
+
+    /// Block until a chunk is available, returning `None` once the writing side has closed and
+    /// all chunks have been consumed or once the reading side has been closed. An error of kind
+    /// `ConnectionReset` is returned if the pipe has been reset.
+    ///
+    /// If `deadline` passes before a chunk is available an error of kind `WouldBlock` is
+    /// returned.
+    pub(crate) fn recv(&self, deadline: Option<Instant>) -> Result<Option<Bytes>> {
+        let mut state = self.pipe.lock();
+        loop {
+            state = match state.pop()? {
+                Delivery::Ready(chunk) => {
+                    self.pipe.notify_writable(&mut state);
+                    return Ok(Some(chunk));
+                }
+                // Wait for the chunk to arrive, unless the caller gives up first
+                Delivery::Delayed(deliver_at) => match deadline {
+                    Some(deadline) if deadline < deliver_at => {
+                        self.pipe.wait(&self.pipe.readable, state, Some(deadline))?
+                    }
+                    _ => {
+                        let timeout = deliver_at.saturating_duration_since(Instant::now());
+                        self.pipe.readable.wait_timeout(state, timeout).unwrap().0
+                    }
+                },
+                Delivery::Empty if state.write_closed || state.read_closed => return Ok(None),
+                Delivery::Empty | Delivery::Held => {
+                    self.pipe.wait(&self.pipe.readable, state, deadline)?
+                }
+            };
+        }
+    }
+
+    /// Poll for the next chunk, registering the current task to be woken once one is available.
+    #[cfg(any(feature = "async", feature = "tokio"))]
+    pub(crate) fn poll_recv(&self, context: &mut Context) -> Poll<Result<Option<Bytes>>> {
+        let mut state = self.pipe.lock();
+        match state.pop()? {
+            Delivery::Ready(chunk) => {
+                self.pipe.notify_writable(&mut state);
+                Poll::Ready(Ok(Some(chunk)))
+            }
+            Delivery::Empty if state.write_closed || state.read_closed => Poll::Ready(Ok(None)),
+            delivery => {
+                if let Delivery::Delayed(deliver_at) = delivery {
+                    state.schedule_read_timer(deliver_at, context.waker());
+                }
+                match &state.read_waker {
+                    Some(waker) if waker.will_wake(context.waker()) => {}
+                    _ => state.read_waker = Some(context.waker().clone()),
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -0,0 +1,425 @@
+//! Recording the traffic of a connection to a transcript, and replaying it with a `MockPeer`.
+
+use crate::{mock::escape, MockPeer};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Result, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// The first line of every transcript file, identifying the version of the format.
+const HEADER: &str = "memory-socket transcript v1";
+
+/// A wrapper around a connection which records everything sent and received through it to a
+/// [`Transcript`].
+///
+/// Each successful read and write is recorded as a separate event, preserving chunk boundaries,
+/// along with the time it happened relative to when the recorder was created. Reaching the end of
+/// the stream is recorded as well. Any `Read + Write` stream can be recorded, such as a
+/// `TcpStream` in production, and the asynchronous IO traits are implemented when the `async` or
+/// `tokio` feature is enabled.
+///
+/// [`Transcript`]: struct.Transcript.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use memory_socket::{MemorySocket, Recorder};
+///
+/// # fn main () -> ::std::io::Result<()> {
+/// let (socket, mut peer) = MemorySocket::new_pair();
+/// let mut recorder = Recorder::new(socket);
+///
+/// recorder.write_all(b"ping")?;
+/// recorder.flush()?;
+///
+/// let mut buf = [0; 4];
+/// peer.read_exact(&mut buf)?;
+/// peer.write_all(b"pong")?;
+/// peer.flush()?;
+///
+/// recorder.read_exact(&mut buf)?;
+///
+/// let (_socket, transcript) = recorder.into_parts();
+/// assert_eq!(transcript.events().len(), 2);
+/// # Ok(())}
+/// ```
+#[derive(Debug)]
+pub struct Recorder<S> {
+    inner: S,
+    start: Instant,
+    transcript: Transcript,
+}
+
+impl<S> Recorder<S> {
+    /// Start recording the traffic on `inner`.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            start: Instant::now(),
+            transcript: Transcript::new(),
+        }
+    }
+
+    /// Returns a reference to the recorded connection.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the recorded connection.
+    ///
+    /// Data read from or written to the connection through this reference isn't recorded.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Returns the transcript recorded so far.
+    pub fn transcript(&self) -> &Transcript {
+        &self.transcript
+    }
+
+    /// Stop recording, returning the connection and its transcript.
+    pub fn into_parts(self) -> (S, Transcript) {
+        (self.inner, self.transcript)
+    }
+
+    /// Record the result of a read of up to `requested` bytes which returned `data`.
+    pub(crate) fn record_read(&mut self, requested: usize, data: &[u8]) {
+        let offset = self.start.elapsed();
+        if !data.is_empty() {
+            self.transcript.events.push(Event::Received {
+                offset,
+                data: data.to_vec(),
+            });
+        } else if requested > 0 {
+            // Only the first read to observe the end of the stream is interesting
+            if let Some(Event::Eof { .. }) = self.transcript.events.last() {
+                return;
+            }
+            self.transcript.events.push(Event::Eof { offset });
+        }
+    }
+
+    /// Record a write which sent `data`.
+    pub(crate) fn record_write(&mut self, data: &[u8]) {
+        if !data.is_empty() {
+            self.transcript.events.push(Event::Sent {
+                offset: self.start.elapsed(),
+                data: data.to_vec(),
+            });
+        }
+    }
+}
+
+impl<S: Read> Read for Recorder<S> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.record_read(buf.len(), &buf[..len]);
+        Ok(len)
+    }
+}
+
+impl<S: Write> Write for Recorder<S> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.record_write(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A single event in a [`Transcript`], from the point of view of the recorded side of the
+/// connection.
+///
+/// [`Transcript`]: struct.Transcript.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Data was written by the recorded side.
+    Sent {
+        /// The time since the start of the recording
+        offset: Duration,
+        data: Vec<u8>,
+    },
+    /// Data was read by the recorded side.
+    Received {
+        /// The time since the start of the recording
+        offset: Duration,
+        data: Vec<u8>,
+    },
+    /// The recorded side reached the end of the stream.
+    Eof {
+        /// The time since the start of the recording
+        offset: Duration,
+    },
+}
+
+impl Event {
+    /// Returns the time at which the event happened, relative to the start of the recording.
+    pub fn offset(&self) -> Duration {
+        match *self {
+            Event::Sent { offset, .. } | Event::Received { offset, .. } | Event::Eof { offset } => {
+                offset
+            }
+        }
+    }
+}
+
+/// The recorded traffic of a connection, produced by a [`Recorder`].
+///
+/// Transcripts can be saved to and loaded from a line-based text format, and replayed with
+/// [`replay`] to get a [`MockPeer`] which plays back the data received by the recorded side while
+/// verifying that its replacement sends the same data. This turns a recorded session into a
+/// regression test for the code on the recorded side.
+///
+/// The text format begins with the line `memory-socket transcript v1`, followed by a line per
+/// event. Each event starts with `>` for data sent by the recorded side or `<` for data received
+/// by it, then the offset in seconds, and then the data as an escaped byte string or `EOF`. Blank
+/// lines and lines starting with `#` are ignored.
+///
+/// ```text
+/// memory-socket transcript v1
+/// > 0.000012000 b"GET / HTTP/1.1\r\n\r\n"
+/// < 0.003400000 b"HTTP/1.1 204 No Content\r\n\r\n"
+/// < 0.003500000 EOF
+/// ```
+///
+/// [`MockPeer`]: struct.MockPeer.html
+/// [`Recorder`]: struct.Recorder.html
+/// [`replay`]: #method.replay
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use memory_socket::Transcript;
+///
+/// # fn main () -> ::std::io::Result<()> {
+/// let transcript = Transcript::read_from(
+///     &b"memory-socket transcript v1\n\
+///        > 0.0 b\"ping\"\n\
+///        < 0.1 b\"pong\"\n\
+///        < 0.1 EOF\n"[..],
+/// )?;
+///
+/// let (mut socket, mock) = transcript.replay().build();
+/// socket.write_all(b"ping")?;
+/// socket.flush()?;
+///
+/// let mut response = Vec::new();
+/// socket.read_to_end(&mut response)?;
+/// assert_eq!(response, b"pong");
+///
+/// mock.join();
+/// # Ok(())}
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Transcript {
+    events: Vec<Event>,
+}
+
+impl Transcript {
+    /// Create a new, empty transcript.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the events in the transcript, in the order they happened.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Append an event to the transcript.
+    pub fn push(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Build a [`MockPeer`] which plays the other side of the recorded connection, without any
+    /// delays.
+    ///
+    /// The peer expects the data sent by the recorded side, responds with the data received by
+    /// it, and closes the connection where the recorded side reached the end of the stream. The
+    /// returned builder can be extended with further steps.
+    ///
+    /// [`MockPeer`]: struct.MockPeer.html
+    pub fn replay(&self) -> MockPeer {
+        self.build_replay(false)
+    }
+
+    /// Build a [`MockPeer`] like [`replay`], which also reproduces the recorded delays between
+    /// events before each response.
+    ///
+    /// [`MockPeer`]: struct.MockPeer.html
+    /// [`replay`]: #method.replay
+    pub fn replay_with_timing(&self) -> MockPeer {
+        self.build_replay(true)
+    }
+
+    fn build_replay(&self, timing: bool) -> MockPeer {
+        let mut mock = MockPeer::new();
+        let mut previous = Duration::from_secs(0);
+        for event in &self.events {
+            if timing {
+                if let Event::Received { .. } | Event::Eof { .. } = event {
+                    let delay = event.offset().checked_sub(previous).unwrap_or_default();
+                    if delay > Duration::from_secs(0) {
+                        mock = mock.wait(delay);
+                    }
+                }
+            }
+            previous = event.offset();
+
+            mock = match event {
+                Event::Sent { data, .. } => mock.expect(data),
+                Event::Received { data, .. } => mock.respond(data),
+                Event::Eof { .. } => mock.close(),
+            };
+        }
+        mock
+    }
+
+    /// Write the transcript to `writer` in the text format.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        for event in &self.events {
+            let offset = event.offset();
+            let (direction, data) = match event {
+                Event::Sent { data, .. } => ('>', escape(data)),
+                Event::Received { data, .. } => ('<', escape(data)),
+                Event::Eof { .. } => ('<', "EOF".to_owned()),
+            };
+            writeln!(
+                writer,
+                "{} {}.{:09} {}",
+                direction,
+                offset.as_secs(),
+                offset.subsec_nanos(),
+                data
+            )?;
+        }
+        writer.flush()
+    }
+
+    /// Read a transcript in the text format from `reader`.
+    ///
+    /// An error of kind `InvalidData` is returned if the transcript is malformed.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        let mut lines = BufReader::new(reader).lines();
+        match lines.next().transpose()? {
+            Some(ref line) if line.trim_end() == HEADER => {}
+            _ => return Err(invalid(1, "missing transcript header")),
+        }
+
+        let mut transcript = Self::new();
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let number = index + 2;
+            transcript
+                .events
+                .push(parse_event(line).map_err(|msg| invalid(number, msg))?);
+        }
+        Ok(transcript)
+    }
+
+    /// Save the transcript to the file at `path` in the text format, replacing any existing file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Load a transcript in the text format from the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::read_from(File::open(path)?)
+    }
+}
+
+fn invalid(line: usize, msg: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid transcript on line {}: {}", line, msg),
+    )
+}
+
+fn parse_event(line: &str) -> ::std::result::Result<Event, &'static str> {
+    let mut parts = line.splitn(3, ' ');
+    let direction = parts.next().unwrap_or_default();
+    let offset = parse_offset(parts.next().ok_or("missing offset")?)?;
+    let data = parts.next().ok_or("missing data")?.trim();
+
+    match (direction, data) {
+        ("<", "EOF") => Ok(Event::Eof { offset }),
+        (">", _) => Ok(Event::Sent {
+            offset,
+            data: unescape(data)?,
+        }),
+        ("<", _) => Ok(Event::Received {
+            offset,
+            data: unescape(data)?,
+        }),
+        _ => Err("expected `>` or `<`"),
+    }
+}
+
+/// Parse an offset in seconds, with up to nanosecond precision.
+fn parse_offset(offset: &str) -> ::std::result::Result<Duration, &'static str> {
+    const INVALID: &str = "invalid offset";
+
+    let (secs, fraction) = match offset.split_once('.') {
+        Some((secs, fraction)) => (secs, fraction),
+        None => (offset, ""),
+    };
+    if secs.is_empty()
+        || fraction.len() > 9
+        || !secs
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return Err(INVALID);
+    }
+
+    let secs = secs.parse().map_err(|_| INVALID)?;
+    let nanos = format!("{:0<9}", fraction).parse().map_err(|_| INVALID)?;
+    Ok(Duration::new(secs, nanos))
+}
+
+/// Parse an escaped byte string literal, as written by `escape`.
+fn unescape(literal: &str) -> ::std::result::Result<Vec<u8>, &'static str> {
+    const INVALID: &str = "invalid byte string";
+
+    let inner = literal
+        .strip_prefix("b\"")
+        .and_then(|literal| literal.strip_suffix('"'))
+        .ok_or(INVALID)?;
+
+    let mut bytes = Vec::with_capacity(inner.len());
+    let mut chars = inner.bytes();
+    while let Some(byte) = chars.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        let escaped = match chars.next().ok_or(INVALID)? {
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'0' => b'\0',
+            byte @ (b'\\' | b'\'' | b'"') => byte,
+            b'x' => {
+                let digits = [chars.next().ok_or(INVALID)?, chars.next().ok_or(INVALID)?];
+                let digits = std::str::from_utf8(&digits).map_err(|_| INVALID)?;
+                u8::from_str_radix(digits, 16).map_err(|_| INVALID)?
+            }
+            _ => return Err(INVALID),
+        };
+        bytes.push(escaped);
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,109 @@
+//! Resolving host names to addresses through a [`MemoryNetwork`]'s registry.
+//!
+//! [`MemoryNetwork`]: ../struct.MemoryNetwork.html
+
+use crate::MemoryNetwork;
+use std::{
+    io::{Error, ErrorKind, Result},
+    net::{IpAddr, SocketAddr},
+};
+
+/// The addresses registered for a host name.
+pub(crate) enum Host {
+    Records(Vec<IpAddr>),
+    /// Resolving the name fails with the given kind of error
+    Failure(ErrorKind),
+}
+
+/// A trait for objects which can be resolved to one or more socket addresses within a
+/// [`MemoryNetwork`].
+///
+/// This is the in-memory counterpart of the standard library's `ToSocketAddrs`. Rather than
+/// querying the system's resolver, host names are looked up in the registry of the network, see
+/// [`MemoryNetwork::register_host`].
+///
+/// It is implemented for `SocketAddr`, `(IpAddr, u16)`, `(&str, u16)`, `"host:port"` strings and
+/// slices of `SocketAddr`s.
+///
+/// [`MemoryNetwork`]: struct.MemoryNetwork.html
+/// [`MemoryNetwork::register_host`]: struct.MemoryNetwork.html#method.register_host
+///
+/// # Examples
+///
+/// ```
+/// use memory_socket::{MemoryNetwork, ToMemoryAddrs};
+///
+/// # fn main () -> ::std::io::Result<()> {
+/// let network = MemoryNetwork::new();
+/// network.register_host("db.internal", "192.51.100.5".parse().unwrap());
+///
+/// let addrs = "db.internal:5432".to_memory_addrs(&network)?;
+/// assert_eq!(addrs, vec!["192.51.100.5:5432".parse().unwrap()]);
+/// # Ok(())}
+/// ```
+pub trait ToMemoryAddrs {
+    /// Resolve this object to the socket addresses it refers to within `network`.
+    fn to_memory_addrs(&self, network: &MemoryNetwork) -> Result<Vec<SocketAddr>>;
+}
+
+impl ToMemoryAddrs for SocketAddr {
+    fn to_memory_addrs(&self, _network: &MemoryNetwork) -> Result<Vec<SocketAddr>> {
+        Ok(vec![*self])
+    }
+}
+
+impl ToMemoryAddrs for (IpAddr, u16) {
+    fn to_memory_addrs(&self, _network: &MemoryNetwork) -> Result<Vec<SocketAddr>> {
+        Ok(vec![SocketAddr::new(self.0, self.1)])
+    }
+}
+
+impl ToMemoryAddrs for (&str, u16) {
+    fn to_memory_addrs(&self, network: &MemoryNetwork) -> Result<Vec<SocketAddr>> {
+        let (host, port) = *self;
+        Ok(network
+            .lookup_host(host)?
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect())
+    }
+}
+
+impl ToMemoryAddrs for str {
+    fn to_memory_addrs(&self, network: &MemoryNetwork) -> Result<Vec<SocketAddr>> {
+        if let Ok(address) = self.parse() {
+            return Ok(vec![address]);
+        }
+
+        let (host, port) = self
+            .rsplit_once(':')
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid socket address"))?;
+        let port = port
+            .parse()
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "invalid port value"))?;
+        (host, port).to_memory_addrs(network)
+    }
+}
+
+impl ToMemoryAddrs for String {
+    fn to_memory_addrs(&self, network: &MemoryNetwork) -> Result<Vec<SocketAddr>> {
+        self.as_str().to_memory_addrs(network)
+    }
+}
+
+impl ToMemoryAddrs for [SocketAddr] {
+    fn to_memory_addrs(&self, _network: &MemoryNetwork) -> Result<Vec<SocketAddr>> {
+        Ok(self.to_vec())
+    }
+}
+
+impl<T: ToMemoryAddrs + ?Sized> ToMemoryAddrs for &T {
+    fn to_memory_addrs(&self, network: &MemoryNetwork) -> Result<Vec<SocketAddr>> {
+        (**self).to_memory_addrs(network)
+    }
+}
+
+/// Normalize a host name so that lookups are case insensitive.
+pub(crate) fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
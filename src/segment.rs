@@ -0,0 +1,104 @@
+use crate::rng::Rng;
+use bytes::Bytes;
+
+/// Controls how the data flushed to an in-memory connection is broken up before the peer reads
+/// it, used to exercise code which must cope with partial reads.
+///
+/// Normally each flush is delivered to the peer as a single chunk, so a read never observes only
+/// part of a message that was written and flushed together. Segmentation splits each flushed
+/// chunk into segments of at most `mtu` bytes, optionally of `random` sizes, and can `coalesce`
+/// segments which have arrived back together so that a single read spans several flushes. Data
+/// is always delivered in the order it was sent.
+///
+/// Segmentation can be applied to a socket pair with [`PairBuilder::segmentation`] or to every
+/// new connection in a network with [`MemoryNetwork::set_segmentation`]. The random choices are
+/// derived from the pair's or network's seed, so they can be reproduced.
+///
+/// [`MemoryNetwork::set_segmentation`]: struct.MemoryNetwork.html#method.set_segmentation
+/// [`PairBuilder::segmentation`]: struct.PairBuilder.html#method.segmentation
+///
+/// # Examples
+///
+/// ```
+/// use memory_socket::{MemorySocket, SegmentConfig};
+///
+/// let segmentation = SegmentConfig::new().mtu(16).random(true).coalesce(true);
+/// let (socket_a, socket_b) = MemorySocket::builder().segmentation(segmentation).pair();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SegmentConfig {
+    mtu: Option<usize>,
+    random: bool,
+    coalesce: bool,
+}
+
+impl SegmentConfig {
+    /// Create a new configuration which leaves flushed chunks intact.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Split flushed data into segments of at most `mtu` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mtu` is `0`.
+    pub fn mtu(mut self, mtu: usize) -> Self {
+        assert!(mtu > 0, "mtu must be greater than zero");
+        self.mtu = Some(mtu);
+        self
+    }
+
+    /// Choose the size of each segment at random, up to the `mtu` if one is set.
+    pub fn random(mut self, random: bool) -> Self {
+        self.random = random;
+        self
+    }
+
+    /// Randomly merge segments which are ready to be read with those following them, up to the
+    /// `mtu` if one is set.
+    pub fn coalesce(mut self, coalesce: bool) -> Self {
+        self.coalesce = coalesce;
+        self
+    }
+}
+
+/// The state of segmentation for one direction of a connection.
+pub(crate) struct Segmenter {
+    config: SegmentConfig,
+    rng: Rng,
+}
+
+impl Segmenter {
+    pub(crate) fn new(config: SegmentConfig, rng: Rng) -> Self {
+        Self { config, rng }
+    }
+
+    /// Split `bytes` into the segments it should be delivered as.
+    pub(crate) fn split(&mut self, mut bytes: Bytes) -> Vec<Bytes> {
+        let mut segments = Vec::new();
+        while !bytes.is_empty() {
+            let max = match self.config.mtu {
+                Some(mtu) => ::std::cmp::min(mtu, bytes.len()),
+                None => bytes.len(),
+            };
+            let len = if self.config.random {
+                1 + self.rng.up_to(max as u64 - 1) as usize
+            } else {
+                max
+            };
+            segments.push(bytes.split_to(len));
+        }
+        segments
+    }
+
+    /// Decide whether a segment of `len` bytes should be merged with a following one of
+    /// `next_len` bytes.
+    pub(crate) fn coalesce(&mut self, len: usize, next_len: usize) -> bool {
+        let fits = match self.config.mtu {
+            Some(mtu) => len + next_len <= mtu,
+            None => true,
+        };
+        self.config.coalesce && fits && self.rng.chance(0.5)
+    }
+}
@@ -0,0 +1,239 @@
+//! Split a [`MemorySocket`] into its read and write halves.
+//!
+//! [`MemorySocket`]: ../struct.MemorySocket.html
+
+use crate::{check_timeout, MemorySocket, Reader, Writer};
+use std::{
+    error::Error,
+    fmt,
+    io::{Read, Result, Write},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+
+/// Borrowed read half of a [`MemorySocket`], created by [`split`].
+///
+/// [`MemorySocket`]: struct.MemorySocket.html
+/// [`split`]: struct.MemorySocket.html#method.split
+pub struct ReadHalf<'a> {
+    pub(crate) reader: &'a mut Reader,
+}
+
+impl<'a> ReadHalf<'a> {
+    /// Returns the socket address of the local half of this connection.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.reader.endpoints.local_addr()
+    }
+
+    /// Returns the socket address of the remote peer of this connection.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.reader.endpoints.peer_addr()
+    }
+}
+
+impl<'a> Read for ReadHalf<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// Borrowed write half of a [`MemorySocket`], created by [`split`].
+///
+/// [`MemorySocket`]: struct.MemorySocket.html
+/// [`split`]: struct.MemorySocket.html#method.split
+pub struct WriteHalf<'a> {
+    pub(crate) writer: &'a mut Writer,
+}
+
+impl<'a> WriteHalf<'a> {
+    /// Returns the socket address of the local half of this connection.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.writer.endpoints.local_addr()
+    }
+
+    /// Returns the socket address of the remote peer of this connection.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.writer.endpoints.peer_addr()
+    }
+}
+
+impl<'a> Write for WriteHalf<'a> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Owned read half of a [`MemorySocket`], created by [`into_split`].
+///
+/// Dropping the read half closes the reading side of the connection, causing the peer's writes
+/// to fail with `BrokenPipe`.
+///
+/// [`into_split`]: struct.MemorySocket.html#method.into_split
+/// [`MemorySocket`]: struct.MemorySocket.html
+pub struct OwnedReadHalf {
+    pub(crate) reader: Reader,
+}
+
+impl OwnedReadHalf {
+    /// Attempts to put the two halves of a `MemorySocket` back together.
+    ///
+    /// If the halves did not originate from the same socket, they are returned in a
+    /// [`ReuniteError`].
+    ///
+    /// [`ReuniteError`]: struct.ReuniteError.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memory_socket::MemorySocket;
+    ///
+    /// let (socket_a, socket_b) = MemorySocket::new_pair();
+    /// let (read_a, write_a) = socket_a.into_split();
+    /// let (read_b, write_b) = socket_b.into_split();
+    ///
+    /// // Halves from different sockets are handed back
+    /// let read_a = match read_a.reunite(write_b) {
+    ///     Ok(_) => unreachable!(),
+    ///     Err(error) => error.0,
+    /// };
+    /// let socket_a = read_a.reunite(write_a).unwrap();
+    /// ```
+    #[allow(clippy::result_large_err)]
+    pub fn reunite(self, other: OwnedWriteHalf) -> std::result::Result<MemorySocket, ReuniteError> {
+        if Arc::ptr_eq(&self.reader.endpoints, &other.writer.endpoints) {
+            Ok(MemorySocket {
+                reader: self.reader,
+                writer: other.writer,
+            })
+        } else {
+            Err(ReuniteError(self, other))
+        }
+    }
+
+    /// Returns the socket address of the local half of this connection.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.reader.endpoints.local_addr()
+    }
+
+    /// Returns the socket address of the remote peer of this connection.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.reader.endpoints.peer_addr()
+    }
+
+    /// Sets the read timeout to the timeout specified, see
+    /// [`MemorySocket::set_read_timeout`].
+    ///
+    /// [`MemorySocket::set_read_timeout`]: struct.MemorySocket.html#method.set_read_timeout
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.reader.timeout = check_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Returns the read timeout of this half.
+    pub fn read_timeout(&self) -> Result<Option<Duration>> {
+        Ok(self.reader.timeout)
+    }
+
+    /// Moves this half into or out of nonblocking mode, see
+    /// [`MemorySocket::set_nonblocking`].
+    ///
+    /// [`MemorySocket::set_nonblocking`]: struct.MemorySocket.html#method.set_nonblocking
+    pub fn set_nonblocking(&mut self, nonblocking: bool) -> Result<()> {
+        self.reader.nonblocking = nonblocking;
+        Ok(())
+    }
+}
+
+impl Read for OwnedReadHalf {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// Owned write half of a [`MemorySocket`], created by [`into_split`].
+///
+/// Dropping the write half shuts down the writing side of the connection, causing the peer to
+/// observe EOF.
+///
+/// [`into_split`]: struct.MemorySocket.html#method.into_split
+/// [`MemorySocket`]: struct.MemorySocket.html
+pub struct OwnedWriteHalf {
+    pub(crate) writer: Writer,
+}
+
+impl OwnedWriteHalf {
+    /// Attempts to put the two halves of a `MemorySocket` back together, see
+    /// [`OwnedReadHalf::reunite`].
+    ///
+    /// [`OwnedReadHalf::reunite`]: struct.OwnedReadHalf.html#method.reunite
+    #[allow(clippy::result_large_err)]
+    pub fn reunite(self, other: OwnedReadHalf) -> std::result::Result<MemorySocket, ReuniteError> {
+        other.reunite(self)
+    }
+
+    /// Returns the socket address of the local half of this connection.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.writer.endpoints.local_addr()
+    }
+
+    /// Returns the socket address of the remote peer of this connection.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.writer.endpoints.peer_addr()
+    }
+
+    /// Sets the write timeout to the timeout specified, see
+    /// [`MemorySocket::set_write_timeout`].
+    ///
+    /// [`MemorySocket::set_write_timeout`]: struct.MemorySocket.html#method.set_write_timeout
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.writer.timeout = check_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Returns the write timeout of this half.
+    pub fn write_timeout(&self) -> Result<Option<Duration>> {
+        Ok(self.writer.timeout)
+    }
+
+    /// Moves this half into or out of nonblocking mode, see
+    /// [`MemorySocket::set_nonblocking`].
+    ///
+    /// [`MemorySocket::set_nonblocking`]: struct.MemorySocket.html#method.set_nonblocking
+    pub fn set_nonblocking(&mut self, nonblocking: bool) -> Result<()> {
+        self.writer.nonblocking = nonblocking;
+        Ok(())
+    }
+}
+
+impl Write for OwnedWriteHalf {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Error indicating that two halves were not from the same socket, and thus could not be
+/// reunited.
+pub struct ReuniteError(pub OwnedReadHalf, pub OwnedWriteHalf);
+
+impl fmt::Debug for ReuniteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ReuniteError(..)")
+    }
+}
+
+impl fmt::Display for ReuniteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("tried to reunite halves that are not from the same socket")
+    }
+}
+
+impl Error for ReuniteError {}
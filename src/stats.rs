@@ -0,0 +1,145 @@
+//! Traffic statistics for sockets and listeners.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// A snapshot of the traffic on a [`MemorySocket`], returned by [`MemorySocket::stats`].
+///
+/// [`MemorySocket`]: struct.MemorySocket.html
+/// [`MemorySocket::stats`]: struct.MemorySocket.html#method.stats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SocketStats {
+    bytes_written: u64,
+    bytes_read: u64,
+    flushes: u64,
+    reads: u64,
+    max_queued: usize,
+}
+
+impl SocketStats {
+    /// Returns the number of bytes written to the socket.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Returns the number of bytes read from the socket.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Returns the number of chunks of data sent to the peer.
+    ///
+    /// Each flush of buffered data sends a single chunk, unless the connection's capacity forces
+    /// the data to be split up.
+    pub fn flushes(&self) -> u64 {
+        self.flushes
+    }
+
+    /// Returns the number of successful read calls made on the socket.
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Returns the largest number of bytes which have been in flight to the peer at once.
+    pub fn max_queued(&self) -> usize {
+        self.max_queued
+    }
+}
+
+/// A snapshot of the traffic on the connections accepted by a [`MemoryListener`], returned by
+/// [`MemoryListener::stats`].
+///
+/// [`MemoryListener`]: struct.MemoryListener.html
+/// [`MemoryListener::stats`]: struct.MemoryListener.html#method.stats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ListenerStats {
+    accepted: u64,
+    bytes_written: u64,
+    bytes_read: u64,
+}
+
+impl ListenerStats {
+    /// Returns the number of connections which have been accepted.
+    pub fn accepted(&self) -> u64 {
+        self.accepted
+    }
+
+    /// Returns the number of bytes written across all accepted connections.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Returns the number of bytes read across all accepted connections.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+}
+
+/// Counters shared by both halves of a socket.
+#[derive(Default)]
+pub(crate) struct Counters {
+    bytes_written: AtomicU64,
+    bytes_read: AtomicU64,
+    reads: AtomicU64,
+    /// The listener which accepted the socket, if any
+    listener: Option<Arc<ListenerCounters>>,
+}
+
+impl Counters {
+    /// Counters for a socket accepted by the listener owning `listener`.
+    pub(crate) fn accepted(listener: Arc<ListenerCounters>) -> Self {
+        listener.accepted.fetch_add(1, Ordering::Relaxed);
+        Self {
+            listener: Some(listener),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn record_write(&self, len: usize) {
+        self.bytes_written.fetch_add(len as u64, Ordering::Relaxed);
+        if let Some(listener) = &self.listener {
+            listener
+                .bytes_written
+                .fetch_add(len as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_read(&self, len: usize) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.bytes_read.fetch_add(len as u64, Ordering::Relaxed);
+        if let Some(listener) = &self.listener {
+            listener.bytes_read.fetch_add(len as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Take a snapshot of the counters, combined with those kept by the outgoing pipe.
+    pub(crate) fn snapshot(&self, flushes: u64, max_queued: usize) -> SocketStats {
+        SocketStats {
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            flushes,
+            reads: self.reads.load(Ordering::Relaxed),
+            max_queued,
+        }
+    }
+}
+
+/// Counters aggregated across the connections accepted by a listener.
+#[derive(Default)]
+pub(crate) struct ListenerCounters {
+    accepted: AtomicU64,
+    bytes_written: AtomicU64,
+    bytes_read: AtomicU64,
+}
+
+impl ListenerCounters {
+    pub(crate) fn snapshot(&self) -> ListenerStats {
+        ListenerStats {
+            accepted: self.accepted.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! A background thread which wakes tasks waiting on delayed data.
+
+use once_cell::sync::Lazy;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    sync::{Condvar, Mutex},
+    task::Waker,
+    thread,
+    time::Instant,
+};
+
+static TIMER: Lazy<&'static Timer> = Lazy::new(|| {
+    let timer: &'static Timer = Box::leak(Box::default());
+    thread::Builder::new()
+        .name("memory-socket-timer".into())
+        .spawn(move || timer.run())
+        .expect("failed to spawn timer thread");
+    timer
+});
+
+/// Wake `waker` once `deadline` has passed.
+///
+/// Every call adds an entry which is kept until it fires, so callers should avoid registering the
+/// same task again for a deadline which is already covered.
+pub(crate) fn wake_at(deadline: Instant, waker: Waker) {
+    let mut entries = TIMER.entries.lock().unwrap();
+    entries.push(Reverse(Entry { deadline, waker }));
+    TIMER.condvar.notify_one();
+}
+
+#[derive(Default)]
+struct Timer {
+    entries: Mutex<BinaryHeap<Reverse<Entry>>>,
+    condvar: Condvar,
+}
+
+impl Timer {
+    fn run(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let mut expired = Vec::new();
+            while let Some(Reverse(entry)) = entries.peek() {
+                if entry.deadline > now {
+                    break;
+                }
+                expired.push(entries.pop().unwrap().0.waker);
+            }
+
+            if !expired.is_empty() {
+                drop(entries);
+                expired.into_iter().for_each(Waker::wake);
+                entries = self.entries.lock().unwrap();
+                continue;
+            }
+
+            entries = match entries.peek() {
+                Some(entry) => {
+                    let timeout = entry.0.deadline - now;
+                    self.condvar.wait_timeout(entries, timeout).unwrap().0
+                }
+                None => self.condvar.wait(entries).unwrap(),
+            };
+        }
+    }
+}
+
+struct Entry {
+    deadline: Instant,
+    waker: Waker,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
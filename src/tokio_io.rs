@@ -0,0 +1,87 @@
+//! Implementations of tokio's IO traits.
+
+use crate::{MemorySocket, OwnedReadHalf, OwnedWriteHalf, ReadHalf, Recorder, WriteHalf};
+use futures_core::ready;
+use std::{
+    io::Result,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Implement `AsyncRead` for types which hold the reading half of a socket in a `reader` field.
+macro_rules! impl_async_read {
+    ($($ty:ty),*) => {$(
+        impl AsyncRead for $ty {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                context: &mut Context,
+                buf: &mut ReadBuf,
+            ) -> Poll<Result<()>> {
+                let bytes_read = ready!(self
+                    .get_mut()
+                    .reader
+                    .poll_read(context, buf.initialize_unfilled()))?;
+                buf.advance(bytes_read);
+                Poll::Ready(Ok(()))
+            }
+        }
+    )*};
+}
+
+/// Implement `AsyncWrite` for types which hold the writing half of a socket in a `writer` field.
+macro_rules! impl_async_write {
+    ($($ty:ty),*) => {$(
+        impl AsyncWrite for $ty {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                context: &mut Context,
+                buf: &[u8],
+            ) -> Poll<Result<usize>> {
+                self.get_mut().writer.poll_write(context, buf)
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<()>> {
+                self.get_mut().writer.poll_flush(context)
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<()>> {
+                self.get_mut().writer.poll_close(context)
+            }
+        }
+    )*};
+}
+
+impl_async_read!(MemorySocket, ReadHalf<'_>, OwnedReadHalf);
+impl_async_write!(MemorySocket, WriteHalf<'_>, OwnedWriteHalf);
+
+impl<S: AsyncRead + Unpin> AsyncRead for Recorder<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<Result<()>> {
+        let recorder = self.get_mut();
+        let filled = buf.filled().len();
+        ready!(Pin::new(recorder.get_mut()).poll_read(context, buf))?;
+        recorder.record_read(buf.capacity() - filled, &buf.filled()[filled..]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Recorder<S> {
+    fn poll_write(self: Pin<&mut Self>, context: &mut Context, buf: &[u8]) -> Poll<Result<usize>> {
+        let recorder = self.get_mut();
+        let len = ready!(Pin::new(recorder.get_mut()).poll_write(context, buf))?;
+        recorder.record_write(&buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<()>> {
+        Pin::new(self.get_mut().get_mut()).poll_flush(context)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<()>> {
+        Pin::new(self.get_mut().get_mut()).poll_shutdown(context)
+    }
+}
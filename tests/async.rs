@@ -3,10 +3,14 @@ use futures::{
     io::{AsyncReadExt, AsyncWriteExt},
     stream::StreamExt,
 };
-use memory_socket::{MemoryListener, MemorySocket};
+use memory_socket::{
+    FlushPolicy, LinkConfig, MemoryListener, MemoryNetwork, MemorySocket, MockPeer, PartitionMode,
+    SegmentConfig,
+};
 use std::{
     io::Result,
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
 };
 
 //
@@ -25,9 +29,9 @@ fn listener_bind() -> Result<()> {
 
 #[test]
 fn bind_unspecified() {
-    // Current implementation does not know how to handle unspecified address
-    let listener_result = MemoryListener::bind("0.0.0.0:0".parse().unwrap());
-    assert!(listener_result.is_err());
+    let listener = MemoryListener::bind("0.0.0.0:0".parse().unwrap()).unwrap();
+    assert!(listener.local_addr().ip().is_unspecified());
+    assert_ne!(listener.local_addr().port(), 0);
 }
 
 #[test]
@@ -95,6 +99,30 @@ fn listener_correctly_frees_port_on_drop() -> Result<()> {
     Ok(())
 }
 
+//
+// MemoryNetwork Tests
+//
+
+#[test]
+fn dropping_network_wakes_pending_read() -> Result<()> {
+    let address: SocketAddr = "192.51.100.4:82".parse().unwrap();
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind(address)?;
+
+    let _dialer = network.connect(address)?;
+    let mut listener_socket = block_on(listener.incoming_stream().next()).unwrap()?;
+
+    let reader = std::thread::spawn(move || {
+        let mut buf = [0; 3];
+        block_on(listener_socket.read(&mut buf))
+    });
+    drop(network);
+
+    assert_eq!(reader.join().unwrap()?, 0);
+
+    Ok(())
+}
+
 //
 // MemorySocket Tests
 //
@@ -203,3 +231,321 @@ fn read_bytes_with_large_buffer() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn flush_is_pending_when_peer_is_full() -> Result<()> {
+    use futures::{task::noop_waker, AsyncWrite};
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    let (mut a, mut b) = MemorySocket::pair_with_capacity(4);
+    let waker = noop_waker();
+    let mut context = Context::from_waker(&waker);
+
+    block_on(a.write_all(b"oathbringer"))?;
+    assert!(Pin::new(&mut a).poll_flush(&mut context).is_pending());
+
+    let mut buf = [0; 4];
+    block_on(b.read_exact(&mut buf))?;
+    assert_eq!(&buf, b"oath");
+    match Pin::new(&mut a).poll_flush(&mut context) {
+        Poll::Pending => {}
+        Poll::Ready(result) => panic!("expected flush to be pending: {:?}", result),
+    }
+
+    let reader = std::thread::spawn(move || {
+        let mut buf = [0; 7];
+        block_on(b.read_exact(&mut buf)).map(|()| buf)
+    });
+    block_on(a.flush())?;
+    assert_eq!(&reader.join().unwrap()?, b"bringer");
+
+    Ok(())
+}
+
+#[test]
+fn close_half_closes_socket() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+
+    block_on(a.write_all(b"way of kings"))?;
+    block_on(a.close())?;
+
+    let mut v = Vec::new();
+    block_on(b.read_to_end(&mut v))?;
+    assert_eq!(v, b"way of kings");
+
+    block_on(b.write_all(b"rhythm of war"))?;
+    block_on(b.flush())?;
+
+    let mut buf = [0; 13];
+    block_on(a.read_exact(&mut buf))?;
+    assert_eq!(&buf, b"rhythm of war");
+
+    Ok(())
+}
+
+#[test]
+fn into_split_across_threads() -> Result<()> {
+    let (a, mut b) = MemorySocket::new_pair();
+    let (mut read_half, mut write_half) = a.into_split();
+
+    let writer = std::thread::spawn(move || {
+        block_on(async {
+            write_half.write_all(b"bondsmith").await?;
+            write_half.close().await
+        })
+    });
+
+    let mut v = Vec::new();
+    block_on(b.read_to_end(&mut v))?;
+    assert_eq!(v, b"bondsmith");
+    writer.join().unwrap()?;
+
+    block_on(b.write_all(b"surgebinder"))?;
+    block_on(b.flush())?;
+    let mut buf = [0; 11];
+    block_on(read_half.read_exact(&mut buf))?;
+    assert_eq!(&buf, b"surgebinder");
+
+    Ok(())
+}
+
+#[test]
+fn link_latency_delays_delivery() -> Result<()> {
+    let link = LinkConfig::new().latency(Duration::from_millis(50));
+    let (mut a, mut b) = MemorySocket::builder().link(link).pair();
+
+    let start = Instant::now();
+    block_on(a.write_all(b"dustbringer"))?;
+    block_on(a.flush())?;
+
+    let mut buf = [0; 11];
+    block_on(b.read_exact(&mut buf))?;
+    assert_eq!(&buf, b"dustbringer");
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    Ok(())
+}
+
+#[test]
+fn repeated_polls_during_link_delay() -> Result<()> {
+    let link = LinkConfig::new().latency(Duration::from_millis(50));
+    let (mut a, mut b) = MemorySocket::builder().link(link).pair();
+    block_on(a.write_all(b"windrunner"))?;
+    block_on(a.flush())?;
+
+    block_on(async {
+        let mut buf = [0; 10];
+        // Polling the same read over and over while the data is delayed still wakes it once
+        let mut read = b.read_exact(&mut buf);
+        for _ in 0..1000 {
+            if futures::poll!(&mut read).is_ready() {
+                break;
+            }
+        }
+        read.await?;
+        assert_eq!(&buf, b"windrunner");
+        Ok(())
+    })
+}
+
+#[test]
+fn healing_partition_wakes_pending_read() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    let mut dialer = network.connect_from(
+        "192.51.100.3:0".parse().unwrap(),
+        "192.51.100.2:80".parse().unwrap(),
+    )?;
+    let mut socket = block_on(listener.accept_async())?;
+
+    let partition = network.partition(
+        vec!["192.51.100.2".parse().unwrap()],
+        vec!["192.51.100.3".parse().unwrap()],
+        PartitionMode::Stall,
+    );
+    block_on(dialer.write_all(b"ishar"))?;
+    block_on(dialer.flush())?;
+
+    let reader = std::thread::spawn(move || {
+        let mut buf = [0; 5];
+        block_on(socket.read_exact(&mut buf)).map(|()| buf)
+    });
+    std::thread::sleep(Duration::from_millis(10));
+    network.heal(partition);
+    assert_eq!(&reader.join().unwrap()?, b"ishar");
+
+    Ok(())
+}
+
+#[test]
+fn shutdown_read_during_stall() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    let mut dialer = network.connect_from(
+        "192.51.100.3:0".parse().unwrap(),
+        "192.51.100.2:80".parse().unwrap(),
+    )?;
+    let mut socket = block_on(listener.accept_async())?;
+
+    network.partition(
+        vec!["192.51.100.2".parse().unwrap()],
+        vec!["192.51.100.3".parse().unwrap()],
+        PartitionMode::Stall,
+    );
+    block_on(dialer.write_all(b"held"))?;
+    block_on(dialer.flush())?;
+
+    socket.shutdown(std::net::Shutdown::Read)?;
+    let mut buf = [0; 4];
+    assert_eq!(block_on(socket.read(&mut buf))?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn dropping_network_during_stall_wakes_pending_read() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    let mut dialer = network.connect_from(
+        "192.51.100.3:0".parse().unwrap(),
+        "192.51.100.2:80".parse().unwrap(),
+    )?;
+    let mut socket = block_on(listener.accept_async())?;
+
+    network.partition(
+        vec!["192.51.100.2".parse().unwrap()],
+        vec!["192.51.100.3".parse().unwrap()],
+        PartitionMode::Stall,
+    );
+    block_on(dialer.write_all(b"held"))?;
+    block_on(dialer.flush())?;
+
+    let reader = std::thread::spawn(move || {
+        let mut buf = [0; 4];
+        block_on(socket.read(&mut buf))
+    });
+    std::thread::sleep(Duration::from_millis(10));
+    drop(network);
+    assert_eq!(reader.join().unwrap()?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn abort_wakes_pending_read() -> Result<()> {
+    let (a, mut b) = MemorySocket::new_pair();
+
+    let reader = std::thread::spawn(move || {
+        let mut buf = [0; 4];
+        block_on(b.read(&mut buf))
+    });
+    std::thread::sleep(Duration::from_millis(10));
+    a.abort();
+
+    assert_eq!(
+        reader.join().unwrap().err().map(|e| e.kind()),
+        Some(std::io::ErrorKind::ConnectionReset)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn close_ends_incoming_stream() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let mut listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    let handle = listener.close_handle();
+
+    let acceptor = std::thread::spawn(move || block_on(listener.incoming_stream().count()));
+    std::thread::sleep(Duration::from_millis(10));
+    handle.close();
+    assert_eq!(acceptor.join().unwrap(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn async_stats() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::builder()
+        .flush_policy(FlushPolicy::Manual)
+        .pair();
+
+    block_on(async {
+        a.write_all(b"hello").await?;
+        a.write_all(b" world").await?;
+        a.flush().await?;
+
+        let mut buf = [0; 11];
+        b.read_exact(&mut buf).await?;
+        Ok::<_, std::io::Error>(())
+    })?;
+
+    let stats = a.stats();
+    assert_eq!(stats.bytes_written(), 11);
+    assert_eq!(stats.flushes(), 1);
+    assert_eq!(stats.max_queued(), 11);
+
+    let stats = b.stats();
+    assert_eq!(stats.bytes_read(), 11);
+    assert_eq!(stats.reads(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn segmentation_splits_reads() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::builder()
+        .segmentation(SegmentConfig::new().mtu(4))
+        .pair();
+
+    block_on(async {
+        a.write_all(b"stormlight").await?;
+        a.flush().await?;
+
+        let mut buf = [0; 10];
+        assert_eq!(b.read(&mut buf).await?, 4);
+        b.read_exact(&mut buf[4..]).await?;
+        assert_eq!(&buf, b"stormlight");
+        Ok(())
+    })
+}
+
+#[test]
+fn writes_are_sent_without_flush() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+
+    block_on(async {
+        a.write_all(b"ping").await?;
+        a.write_all(b"pong").await?;
+
+        let mut buf = [0; 8];
+        b.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"pingpong");
+        Ok(())
+    })
+}
+
+#[test]
+fn mock_peer() -> Result<()> {
+    let (mut socket, mock) = MockPeer::new()
+        .expect(b"ping")
+        .respond(b"pong")
+        .close()
+        .build();
+
+    block_on(async {
+        socket.write_all(b"ping").await?;
+        socket.flush().await?;
+
+        let mut response = Vec::new();
+        socket.read_to_end(&mut response).await?;
+        assert_eq!(response, b"pong");
+        Ok::<_, std::io::Error>(())
+    })?;
+
+    mock.join();
+    Ok(())
+}
@@ -0,0 +1,212 @@
+use memory_socket::{ListenerBuilder, MemoryNetwork};
+use std::{
+    io::{ErrorKind, Read, Result, Write},
+    sync::{Arc, Mutex},
+};
+
+/// A writer whose contents can be inspected after handing it to the network.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Split a pcapng capture into its blocks, returning the type and body of each.
+fn blocks(capture: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+    while offset < capture.len() {
+        let kind = u32_at(capture, offset);
+        let length = u32_at(capture, offset + 4) as usize;
+        assert_eq!(u32_at(capture, offset + length - 4) as usize, length);
+        blocks.push((kind, &capture[offset + 8..offset + length - 4]));
+        offset += length;
+    }
+    blocks
+}
+
+/// The IP packets recorded in a capture.
+fn packets(capture: &[u8]) -> Vec<&[u8]> {
+    blocks(capture)
+        .into_iter()
+        .filter(|(kind, _)| *kind == 6)
+        .map(|(_, body)| &body[20..20 + u32_at(body, 12) as usize])
+        .collect()
+}
+
+/// The internet checksum of `bytes`, which is zero if `bytes` includes a valid checksum.
+fn checksum(bytes: &[u8]) -> u16 {
+    let mut sum = bytes
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum::<u32>();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[test]
+fn capture_headers() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let buffer = SharedBuffer::default();
+    network.capture(buffer.clone())?;
+    network.stop_capture()?;
+
+    let capture = buffer.0.lock().unwrap();
+    let blocks = blocks(&capture);
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[0].0, 0x0A0D_0D0A);
+    assert_eq!(u32_at(blocks[0].1, 0), 0x1A2B_3C4D);
+    assert_eq!(blocks[1].0, 1);
+    // LINKTYPE_RAW
+    assert_eq!(&blocks[1].1[..2], &101u16.to_le_bytes());
+
+    Ok(())
+}
+
+#[test]
+fn capture_tcp_stream() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let buffer = SharedBuffer::default();
+    network.capture(buffer.clone())?;
+
+    let listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    let mut client =
+        network.connect_from("192.51.100.3:4000".parse().unwrap(), listener.local_addr())?;
+    let mut server = listener.try_accept()?;
+
+    client.write_all(b"ping")?;
+    client.flush()?;
+    let mut buf = [0; 4];
+    server.read_exact(&mut buf)?;
+    server.write_all(b"pong!")?;
+    server.flush()?;
+    drop(client);
+    drop(server);
+    network.stop_capture()?;
+
+    let capture = buffer.0.lock().unwrap();
+    let packets = packets(&capture);
+    // Flags, sequence number, acknowledgement number and payload of each TCP segment
+    let segments: Vec<_> = packets
+        .iter()
+        .map(|packet| {
+            assert_eq!(packet[0] >> 4, 4);
+            assert_eq!(packet[9], 6);
+            assert_eq!(checksum(&packet[..20]), 0);
+            let tcp = &packet[20..];
+
+            let mut pseudo_header = packet[12..20].to_vec();
+            pseudo_header.extend_from_slice(&[0, 6, 0, tcp.len() as u8]);
+            pseudo_header.extend_from_slice(tcp);
+            assert_eq!(checksum(&pseudo_header), 0);
+
+            let seq = u32::from_be_bytes([tcp[4], tcp[5], tcp[6], tcp[7]]);
+            let ack = u32::from_be_bytes([tcp[8], tcp[9], tcp[10], tcp[11]]);
+            (tcp[13], seq, ack, &tcp[20..])
+        })
+        .collect();
+
+    let client_to_server = &packets[0][12..20];
+    assert_eq!(client_to_server, &[192, 51, 100, 3, 192, 51, 100, 2]);
+    assert_eq!(
+        segments,
+        vec![
+            (0x02, 0, 0, &b""[..]),
+            (0x12, 0, 1, &b""[..]),
+            (0x10, 1, 1, &b""[..]),
+            (0x18, 1, 1, &b"ping"[..]),
+            (0x18, 1, 5, &b"pong!"[..]),
+            (0x11, 5, 6, &b""[..]),
+            (0x11, 6, 6, &b""[..]),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn capture_reset_and_ipv6() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let buffer = SharedBuffer::default();
+    network.capture(buffer.clone())?;
+
+    let listener = network.bind("[2001:db8::1]:80".parse().unwrap())?;
+    let client = network.connect(listener.local_addr())?;
+    let _server = listener.try_accept()?;
+    client.abort();
+    network.stop_capture()?;
+
+    let capture = buffer.0.lock().unwrap();
+    let packets = packets(&capture);
+    assert_eq!(packets.len(), 4);
+    assert!(packets.iter().all(|packet| packet[0] >> 4 == 6));
+    // Only a single RST is recorded for the connection
+    assert_eq!(packets[3][40 + 13] & 0x04, 0x04);
+
+    Ok(())
+}
+
+#[test]
+fn capture_refused_connection() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let buffer = SharedBuffer::default();
+    network.capture(buffer.clone())?;
+
+    let listener = ListenerBuilder::new()
+        .backlog(1)
+        .bind_in(&network, "192.51.100.2:80".parse().unwrap())?;
+    let _queued =
+        network.connect_from("192.51.100.3:4000".parse().unwrap(), listener.local_addr())?;
+    assert_eq!(
+        network
+            .connect_from("192.51.100.3:4001".parse().unwrap(), listener.local_addr())
+            .err()
+            .map(|e| e.kind()),
+        Some(ErrorKind::ConnectionRefused)
+    );
+    network.stop_capture()?;
+
+    let capture = buffer.0.lock().unwrap();
+    // Source port and flags of each TCP segment
+    let segments: Vec<_> = packets(&capture)
+        .iter()
+        .map(|packet| {
+            (
+                u16::from_be_bytes([packet[20], packet[21]]),
+                packet[20 + 13],
+            )
+        })
+        .collect();
+    assert_eq!(
+        segments,
+        vec![
+            (4000, 0x02),
+            (80, 0x12),
+            (4000, 0x10),
+            // The refused connection is never established
+            (4001, 0x02),
+            (80, 0x14),
+        ]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,304 @@
+use memory_socket::{MemoryDatagramSocket, MemoryNetwork, PartitionMode};
+use std::{
+    io::{ErrorKind, Result},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+
+#[test]
+fn default_network() -> Result<()> {
+    let socket = MemoryDatagramSocket::bind("192.51.100.2:5300".parse().unwrap())?;
+    socket.send_to(b"rosharan", socket.local_addr())?;
+
+    let mut buf = [0; 8];
+    assert_eq!(socket.recv_from(&mut buf)?, (8, socket.local_addr()));
+
+    Ok(())
+}
+
+#[test]
+fn bind_port_zero() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let a = network.bind_datagram("192.51.100.2:0".parse().unwrap())?;
+    let b = network.bind_datagram("192.51.100.2:0".parse().unwrap())?;
+
+    assert_ne!(a.local_addr().port(), 0);
+    assert_ne!(a.local_addr(), b.local_addr());
+
+    Ok(())
+}
+
+#[test]
+fn bind_twice() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let address: SocketAddr = "192.51.100.2:53".parse().unwrap();
+    let socket = network.bind_datagram(address)?;
+
+    assert_eq!(
+        network.bind_datagram(address).err().map(|e| e.kind()),
+        Some(ErrorKind::AddrInUse)
+    );
+
+    drop(socket);
+    network.bind_datagram(address)?;
+
+    Ok(())
+}
+
+#[test]
+fn shares_address_with_listener() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("192.51.100.2:0".parse().unwrap())?;
+    let socket = network.bind_datagram(listener.local_addr())?;
+
+    assert_eq!(socket.local_addr(), listener.local_addr());
+
+    Ok(())
+}
+
+#[test]
+fn preserves_message_boundaries() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let a = network.bind_datagram("192.51.100.2:0".parse().unwrap())?;
+    let b = network.bind_datagram("192.51.100.3:0".parse().unwrap())?;
+
+    a.send_to(b"dalinar", b.local_addr())?;
+    a.send_to(b"navani", b.local_addr())?;
+
+    let mut buf = [0; 32];
+    assert_eq!(b.recv_from(&mut buf)?, (7, a.local_addr()));
+    assert_eq!(&buf[..7], b"dalinar");
+    assert_eq!(b.recv_from(&mut buf)?, (6, a.local_addr()));
+    assert_eq!(&buf[..6], b"navani");
+
+    Ok(())
+}
+
+#[test]
+fn truncates_large_messages() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let socket = network.bind_datagram("192.51.100.2:0".parse().unwrap())?;
+
+    socket.send_to(b"shallan davar", socket.local_addr())?;
+    socket.send_to(b"veil", socket.local_addr())?;
+
+    let mut buf = [0; 7];
+    assert_eq!(socket.recv_from(&mut buf)?.0, 7);
+    assert_eq!(&buf, b"shallan");
+
+    // The rest of the truncated message was discarded
+    assert_eq!(socket.recv_from(&mut buf)?.0, 4);
+    assert_eq!(&buf[..4], b"veil");
+
+    Ok(())
+}
+
+#[test]
+fn send_to_unbound_address_is_dropped() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let socket = network.bind_datagram("192.51.100.2:0".parse().unwrap())?;
+
+    assert_eq!(
+        socket.send_to(b"void", "192.51.100.9:9".parse().unwrap())?,
+        4
+    );
+
+    Ok(())
+}
+
+#[test]
+fn connected_sockets() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let a = network.bind_datagram("192.51.100.2:0".parse().unwrap())?;
+    let b = network.bind_datagram("192.51.100.3:0".parse().unwrap())?;
+    let c = network.bind_datagram("192.51.100.4:0".parse().unwrap())?;
+
+    let mut buf = [0; 8];
+    assert_eq!(
+        a.send(b"nope").err().map(|e| e.kind()),
+        Some(ErrorKind::NotConnected)
+    );
+    assert_eq!(
+        a.recv(&mut buf).err().map(|e| e.kind()),
+        Some(ErrorKind::NotConnected)
+    );
+
+    a.connect(b.local_addr())?;
+    assert_eq!(a.peer_addr()?, b.local_addr());
+
+    // Messages from anyone other than the peer are discarded
+    c.send_to(b"intruder", a.local_addr())?;
+    b.send_to(b"adolin", a.local_addr())?;
+    assert_eq!(a.recv(&mut buf)?, 6);
+    assert_eq!(&buf[..6], b"adolin");
+
+    a.send(b"renarin")?;
+    assert_eq!(b.recv_from(&mut buf)?, (7, a.local_addr()));
+
+    Ok(())
+}
+
+#[test]
+fn read_timeout_and_nonblocking() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let socket = network.bind_datagram("192.51.100.2:0".parse().unwrap())?;
+    let mut buf = [0; 4];
+
+    socket.set_read_timeout(Some(Duration::from_millis(10)))?;
+    assert_eq!(socket.read_timeout()?, Some(Duration::from_millis(10)));
+    assert_eq!(
+        socket.recv_from(&mut buf).err().map(|e| e.kind()),
+        Some(ErrorKind::WouldBlock)
+    );
+
+    socket.set_nonblocking(true)?;
+    assert_eq!(
+        socket.recv_from(&mut buf).err().map(|e| e.kind()),
+        Some(ErrorKind::WouldBlock)
+    );
+    socket.send_to(b"jasnah", socket.local_addr())?;
+    assert_eq!(socket.recv_from(&mut buf)?.0, 4);
+
+    Ok(())
+}
+
+#[test]
+fn shared_between_threads() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let socket = Arc::new(network.bind_datagram("192.51.100.2:0".parse().unwrap())?);
+    let echo = network.bind_datagram("192.51.100.3:0".parse().unwrap())?;
+    let echo_addr = echo.local_addr();
+
+    // One thread waits for the reply while another sends the request on the same socket
+    let receiver = {
+        let socket = socket.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0; 8];
+            socket
+                .recv_from(&mut buf)
+                .map(|(len, source)| (buf[..len].to_vec(), source))
+        })
+    };
+    let sender = {
+        let socket = socket.clone();
+        std::thread::spawn(move || socket.send_to(b"kholin", echo_addr))
+    };
+    assert_eq!(sender.join().unwrap()?, 6);
+
+    let mut buf = [0; 8];
+    let (len, source) = echo.recv_from(&mut buf)?;
+    assert_eq!(source, socket.local_addr());
+    echo.send_to(&buf[..len], source)?;
+
+    assert_eq!(receiver.join().unwrap()?, (b"kholin".to_vec(), echo_addr));
+
+    Ok(())
+}
+
+#[test]
+fn dropping_network_aborts_recv() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let socket = network.bind_datagram("192.51.100.2:0".parse().unwrap())?;
+
+    drop(network);
+
+    let mut buf = [0; 4];
+    assert_eq!(
+        socket.recv_from(&mut buf).err().map(|e| e.kind()),
+        Some(ErrorKind::ConnectionAborted)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn wildcard_socket_receives_for_any_ip() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let wildcard = network.bind_datagram("0.0.0.0:53".parse().unwrap())?;
+    let exact = network.bind_datagram("192.51.100.2:53".parse().unwrap())?;
+    let client = network.bind_datagram("192.51.100.9:0".parse().unwrap())?;
+
+    client.send_to(b"exact", "192.51.100.2:53".parse().unwrap())?;
+    client.send_to(b"wild", "192.51.100.3:53".parse().unwrap())?;
+
+    let mut buf = [0; 8];
+    assert_eq!(exact.recv_from(&mut buf)?.0, 5);
+    assert_eq!(wildcard.recv_from(&mut buf)?.0, 4);
+
+    Ok(())
+}
+
+#[test]
+fn connected_socket_talks_to_wildcard_socket() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let server = network.bind_datagram("0.0.0.0:53".parse().unwrap())?;
+    let client = network.bind_datagram("10.0.0.9:0".parse().unwrap())?;
+    client.connect("10.0.0.1:53".parse().unwrap())?;
+    client.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+    // Replies come from the IP the client sent to
+    client.send(b"query")?;
+    let mut buf = [0; 8];
+    let (len, source) = server.recv_from(&mut buf)?;
+    assert_eq!(source, client.local_addr());
+    server.send_to(&buf[..len], source)?;
+    assert_eq!(client.recv(&mut buf)?, 5);
+
+    // A wildcard sender without an earlier message uses the destination's IP
+    let wildcard_client = network.bind_datagram("0.0.0.0:0".parse().unwrap())?;
+    wildcard_client.send_to(b"query", "10.0.0.1:53".parse().unwrap())?;
+    let (_, source) = server.recv_from(&mut buf)?;
+    assert_eq!(source.ip(), "10.0.0.1".parse::<std::net::IpAddr>().unwrap());
+    assert_eq!(source.port(), wildcard_client.local_addr().port());
+
+    // Partitions apply to the resolved source
+    network.partition(
+        vec!["10.0.0.1".parse().unwrap()],
+        vec!["10.0.0.9".parse().unwrap()],
+        PartitionMode::Stall,
+    );
+    server.send_to(b"lost", client.local_addr())?;
+    assert_eq!(
+        client.recv(&mut buf).unwrap_err().kind(),
+        ErrorKind::WouldBlock
+    );
+
+    Ok(())
+}
+
+/// Send `count` datagrams to `receiver`, returning which of them arrived.
+fn delivered(network: &MemoryNetwork, count: u8) -> Result<Vec<u8>> {
+    let sender = network.bind_datagram("192.51.100.2:0".parse().unwrap())?;
+    let receiver = network.bind_datagram("192.51.100.3:0".parse().unwrap())?;
+    receiver.set_nonblocking(true)?;
+
+    for i in 0..count {
+        sender.send_to(&[i], receiver.local_addr())?;
+    }
+
+    let mut delivered = Vec::new();
+    let mut buf = [0; 1];
+    while let Ok((_, _)) = receiver.recv_from(&mut buf) {
+        delivered.push(buf[0]);
+    }
+    Ok(delivered)
+}
+
+#[test]
+fn datagram_loss_is_reproducible() -> Result<()> {
+    let network = MemoryNetwork::with_seed(1234);
+    network.set_datagram_loss(0.5);
+    let first = delivered(&network, 100)?;
+    assert!(!first.is_empty() && first.len() < 100);
+
+    let network = MemoryNetwork::with_seed(1234);
+    network.set_datagram_loss(0.5);
+    assert_eq!(delivered(&network, 100)?, first);
+
+    let network = MemoryNetwork::with_seed(1234);
+    network.set_datagram_loss(0.0);
+    assert_eq!(delivered(&network, 100)?.len(), 100);
+
+    Ok(())
+}
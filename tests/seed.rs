@@ -0,0 +1,19 @@
+use memory_socket::{MemoryListener, MemoryNetwork};
+use std::io::Result;
+
+// The process seed is read once, so this binary holds a single test which sets it before
+// anything else touches the default network.
+#[test]
+fn default_network_uses_seed_from_env() -> Result<()> {
+    std::env::set_var("MEMORY_SOCKET_SEED", "1234");
+
+    let default = MemoryNetwork::default_network();
+    assert_eq!(default.seed(), Some(1234));
+
+    let listener = MemoryListener::bind("192.51.100.2:0".parse().unwrap())?;
+    let replay = MemoryNetwork::with_seed(1234);
+    let replayed = replay.bind("192.51.100.2:0".parse().unwrap())?;
+    assert_eq!(listener.local_addr(), replayed.local_addr());
+
+    Ok(())
+}
@@ -1,7 +1,11 @@
-use memory_socket::{MemoryListener, MemorySocket};
+use memory_socket::{
+    DiscardPolicy, Event, FlushPolicy, LinkConfig, ListenerBuilder, MemoryListener, MemoryNetwork,
+    MemorySocket, MockPeer, PairBuilder, PartitionMode, Recorder, SegmentConfig, Transcript,
+};
 use std::{
-    io::{Read, Result, Write},
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    io::{ErrorKind, Read, Result, Write},
+    net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr},
+    time::{Duration, Instant},
 };
 
 //
@@ -21,9 +25,9 @@ fn listener_bind() -> Result<()> {
 
 #[test]
 fn bind_unspecified() {
-    // Current implementation does not know how to handle unspecified address
-    let listener_result = MemoryListener::bind("0.0.0.0:0".parse().unwrap());
-    assert!(listener_result.is_err());
+    let listener = MemoryListener::bind("0.0.0.0:0".parse().unwrap()).unwrap();
+    assert!(listener.local_addr().ip().is_unspecified());
+    assert_ne!(listener.local_addr().port(), 0);
 }
 
 #[test]
@@ -97,6 +101,148 @@ fn listener_correctly_frees_port_on_drop() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn accept_timeout() -> Result<()> {
+    let listener = MemoryListener::bind("192.51.100.3:10".parse().unwrap())?;
+
+    let error = listener
+        .accept_timeout(Duration::from_millis(10))
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), ErrorKind::TimedOut);
+    assert_eq!(
+        listener.try_accept().err().map(|e| e.kind()),
+        Some(ErrorKind::WouldBlock)
+    );
+
+    let _dialer = MemorySocket::connect(listener.local_addr())?;
+    listener.accept_timeout(Duration::from_millis(10))?;
+
+    Ok(())
+}
+
+#[test]
+fn connected_socket_addresses() -> Result<()> {
+    let listener = MemoryListener::bind("192.51.100.3:12".parse().unwrap())?;
+
+    let dialer_a = MemorySocket::connect(listener.local_addr())?;
+    let dialer_b = MemorySocket::connect(listener.local_addr())?;
+    let listener_socket_a = listener.accept()?;
+    let listener_socket_b = listener.accept()?;
+
+    assert_eq!(dialer_a.peer_addr()?, listener.local_addr());
+    assert_eq!(dialer_a.local_addr()?.ip(), listener.local_addr().ip());
+    assert_ne!(dialer_a.local_addr()?, dialer_b.local_addr()?);
+    assert_eq!(listener_socket_a.local_addr()?, listener.local_addr());
+    assert_eq!(listener_socket_a.peer_addr()?, dialer_a.local_addr()?);
+    assert_eq!(listener_socket_b.peer_addr()?, dialer_b.local_addr()?);
+
+    Ok(())
+}
+
+#[test]
+fn connect_from_reserves_local_port() -> Result<()> {
+    let listener = MemoryListener::bind("192.51.100.3:13".parse().unwrap())?;
+    let local_address: SocketAddr = "192.51.100.8:5000".parse().unwrap();
+
+    let dialer = MemorySocket::connect_from(local_address, listener.local_addr())?;
+    let listener_socket = listener.accept()?;
+    assert_eq!(dialer.local_addr()?, local_address);
+    assert_eq!(listener_socket.peer_addr()?, local_address);
+
+    // The local port is in use until the socket is dropped
+    let result = MemorySocket::connect_from(local_address, listener.local_addr());
+    assert_eq!(result.err().map(|e| e.kind()), Some(ErrorKind::AddrInUse));
+    assert_eq!(
+        MemoryListener::bind(local_address).err().map(|e| e.kind()),
+        Some(ErrorKind::AddrInUse)
+    );
+
+    drop(dialer);
+    MemorySocket::connect_from(local_address, listener.local_addr())?;
+
+    Ok(())
+}
+
+//
+// MemoryNetwork Tests
+//
+
+#[test]
+fn networks_are_isolated() -> Result<()> {
+    let address: SocketAddr = "192.51.100.4:80".parse().unwrap();
+    let network_a = MemoryNetwork::new();
+    let network_b = MemoryNetwork::new();
+
+    let listener_a = network_a.bind(address)?;
+    let listener_b = network_b.bind(address)?;
+
+    let mut dialer = network_b.connect(address)?;
+    let mut listener_socket = listener_b.accept()?;
+
+    dialer.write_all(b"foo")?;
+    dialer.flush()?;
+
+    let mut buf = [0; 3];
+    listener_socket.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"foo");
+
+    // Unbinding in one network leaves the other untouched
+    drop(listener_b);
+    assert!(network_b.connect(address).is_err());
+    network_a.connect(address)?;
+    listener_a.accept()?;
+
+    Ok(())
+}
+
+#[test]
+fn dropping_network_tears_down_sockets() -> Result<()> {
+    let address: SocketAddr = "192.51.100.4:81".parse().unwrap();
+    let network = MemoryNetwork::new();
+    let listener = network.bind(address)?;
+
+    let mut dialer = network.connect(address)?;
+    let mut listener_socket = listener.accept()?;
+
+    dialer.write_all(b"foo")?;
+    dialer.flush()?;
+    drop(network);
+
+    let mut buf = [0; 3];
+    assert_eq!(listener_socket.read(&mut buf)?, 0);
+    assert_eq!(
+        dialer.write(b"bar").unwrap_err().kind(),
+        ErrorKind::BrokenPipe
+    );
+    assert_eq!(
+        listener.accept().err().map(|e| e.kind()),
+        Some(ErrorKind::ConnectionAborted)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn dropping_network_closes_queued_connections() -> Result<()> {
+    let address: SocketAddr = "192.51.100.4:83".parse().unwrap();
+    let network = MemoryNetwork::new();
+    let _listener = network.bind(address)?;
+
+    // The connection is queued but never accepted
+    let mut dialer = network.connect(address)?;
+    drop(network);
+
+    let mut buf = [0; 3];
+    assert_eq!(dialer.read(&mut buf)?, 0);
+    assert_eq!(
+        dialer.write(b"bar").unwrap_err().kind(),
+        ErrorKind::BrokenPipe
+    );
+
+    Ok(())
+}
+
 //
 // MemorySocket Tests
 //
@@ -205,3 +351,1362 @@ fn read_bytes_with_large_buffer() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn flush_blocks_when_peer_is_full() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::pair_with_capacity(4);
+
+    let writer = std::thread::spawn(move || -> Result<()> {
+        a.write_all(b"words of radiance")?;
+        a.flush()
+    });
+
+    // The writer can't make progress until we start reading
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert!(!writer.is_finished());
+
+    let mut buf = [0; 17];
+    b.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"words of radiance");
+    writer.join().unwrap()?;
+
+    Ok(())
+}
+
+#[test]
+fn flush_to_dropped_peer_with_full_buffer() -> Result<()> {
+    let (mut a, b) = MemorySocket::pair_with_capacity(4);
+
+    let writer = std::thread::spawn(move || -> Result<()> {
+        a.write_all(b"words of radiance")?;
+        a.flush()
+    });
+    drop(b);
+
+    let error = writer.join().unwrap().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+
+    Ok(())
+}
+
+#[test]
+fn shutdown_write_half_close() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+
+    a.write_all(b"way of kings")?;
+    a.shutdown(Shutdown::Write)?;
+    assert_eq!(a.write(b"more").unwrap_err().kind(), ErrorKind::BrokenPipe);
+
+    let mut v = Vec::new();
+    b.read_to_end(&mut v)?;
+    assert_eq!(v, b"way of kings");
+
+    // The other direction is still open
+    b.write_all(b"words of radiance")?;
+    b.flush()?;
+
+    let mut buf = [0; 17];
+    a.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"words of radiance");
+
+    Ok(())
+}
+
+#[test]
+fn shutdown_read() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+
+    b.write_all(b"edgedancer")?;
+    b.flush()?;
+    a.shutdown(Shutdown::Read)?;
+
+    let mut buf = [0; 10];
+    assert_eq!(a.read(&mut buf)?, 0);
+
+    assert_eq!(
+        b.write(b"oathbringer").unwrap_err().kind(),
+        ErrorKind::BrokenPipe
+    );
+
+    Ok(())
+}
+
+#[test]
+fn read_timeout() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+
+    assert_eq!(
+        a.set_read_timeout(Some(Duration::from_secs(0)))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+    a.set_read_timeout(Some(Duration::from_millis(10)))?;
+    assert_eq!(a.read_timeout()?, Some(Duration::from_millis(10)));
+
+    let mut buf = [0; 4];
+    assert_eq!(a.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+    // The socket is still usable after a timeout
+    b.write_all(b"odium")?;
+    b.flush()?;
+    a.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"odiu");
+
+    Ok(())
+}
+
+#[test]
+fn write_timeout_keeps_unsent_data() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::pair_with_capacity(4);
+    a.set_write_timeout(Some(Duration::from_millis(10)))?;
+
+    a.write_all(b"honorspren")?;
+    assert_eq!(a.flush().unwrap_err().kind(), ErrorKind::WouldBlock);
+
+    let reader = std::thread::spawn(move || -> Result<Vec<u8>> {
+        let mut buf = [0; 10];
+        b.read_exact(&mut buf)?;
+        Ok(buf.to_vec())
+    });
+    a.set_write_timeout(None)?;
+    a.flush()?;
+    assert_eq!(reader.join().unwrap()?, b"honorspren");
+
+    Ok(())
+}
+
+#[test]
+fn nonblocking_socket() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::pair_with_capacity(4);
+    a.set_nonblocking(true)?;
+
+    let mut buf = [0; 4];
+    assert_eq!(a.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+    // Only the data which fits is sent, the remainder stays buffered
+    a.write_all(b"cosmere")?;
+    assert_eq!(a.flush().unwrap_err().kind(), ErrorKind::WouldBlock);
+    b.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"cosm");
+    a.flush()?;
+
+    let mut buf = [0; 3];
+    b.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"ere");
+
+    Ok(())
+}
+
+#[test]
+fn nonblocking_listener() -> Result<()> {
+    let listener = MemoryListener::bind("192.51.100.3:11".parse().unwrap())?;
+    listener.set_nonblocking(true)?;
+
+    assert_eq!(
+        listener.accept().err().map(|e| e.kind()),
+        Some(ErrorKind::WouldBlock)
+    );
+
+    let _dialer = MemorySocket::connect(listener.local_addr())?;
+    assert!(listener.incoming().next().unwrap().is_ok());
+    assert_eq!(
+        listener.incoming().next().unwrap().err().map(|e| e.kind()),
+        Some(ErrorKind::WouldBlock)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn pair_has_no_addresses() {
+    let (a, _b) = MemorySocket::new_pair();
+
+    assert_eq!(
+        a.local_addr().unwrap_err().kind(),
+        ErrorKind::AddrNotAvailable
+    );
+    assert_eq!(
+        a.peer_addr().unwrap_err().kind(),
+        ErrorKind::AddrNotAvailable
+    );
+}
+
+#[test]
+fn split_read_write() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+    let (mut read_half, mut write_half) = a.split();
+
+    write_half.write_all(b"shardblade")?;
+    write_half.flush()?;
+    let mut buf = [0; 10];
+    b.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"shardblade");
+
+    b.write_all(b"shardplate")?;
+    b.flush()?;
+    read_half.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"shardplate");
+
+    Ok(())
+}
+
+#[test]
+fn into_split_across_threads() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("192.51.100.3:42".parse().unwrap())?;
+    let socket = network.connect("192.51.100.3:42".parse().unwrap())?;
+    let mut peer = listener.accept()?;
+
+    let (mut read_half, mut write_half) = socket.into_split();
+    assert_eq!(read_half.peer_addr()?, "192.51.100.3:42".parse().unwrap());
+    assert_eq!(write_half.local_addr()?, read_half.local_addr()?);
+
+    let writer = std::thread::spawn(move || -> Result<()> {
+        write_half.write_all(b"honor")?;
+        write_half.flush()
+    });
+    let mut buf = [0; 5];
+    peer.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"honor");
+    writer.join().unwrap()?;
+
+    // Dropping the write half causes the peer to observe EOF
+    assert_eq!(peer.read(&mut buf)?, 0);
+
+    peer.write_all(b"odium")?;
+    peer.flush()?;
+    read_half.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"odium");
+
+    Ok(())
+}
+
+#[test]
+fn reunite() -> Result<()> {
+    let (a, mut b) = MemorySocket::new_pair();
+    let (c, _d) = MemorySocket::new_pair();
+    let (read_a, write_a) = a.into_split();
+    let (read_c, write_c) = c.into_split();
+
+    let error = match read_a.reunite(write_c) {
+        Ok(_) => panic!("reunited halves of different sockets"),
+        Err(error) => error,
+    };
+    assert!(error.1.reunite(read_c).is_ok());
+
+    let mut a = write_a.reunite(error.0).unwrap();
+    a.write_all(b"cultivation")?;
+    a.flush()?;
+    let mut buf = [0; 11];
+    b.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"cultivation");
+
+    Ok(())
+}
+
+#[test]
+fn link_latency_delays_delivery() -> Result<()> {
+    let link = LinkConfig::new().latency(Duration::from_millis(50));
+    let (mut a, mut b) = MemorySocket::builder().link(link).pair();
+
+    let start = Instant::now();
+    a.write_all(b"windrunner")?;
+    a.flush()?;
+
+    // Data still in transit isn't visible to a reader which gives up early
+    b.set_read_timeout(Some(Duration::from_millis(10)))?;
+    let mut buf = [0; 10];
+    assert_eq!(
+        b.read(&mut buf).err().map(|e| e.kind()),
+        Some(ErrorKind::WouldBlock)
+    );
+
+    b.set_read_timeout(None)?;
+    b.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"windrunner");
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    Ok(())
+}
+
+#[test]
+fn link_jitter_preserves_order() -> Result<()> {
+    let link = LinkConfig::new().jitter(Duration::from_millis(5));
+    let (mut a, mut b) = MemorySocket::builder().link(link).pair();
+
+    for i in 0..32u8 {
+        a.write_all(&[i])?;
+        a.flush()?;
+    }
+
+    let mut buf = [0; 32];
+    b.read_exact(&mut buf)?;
+    assert_eq!(buf.to_vec(), (0..32).collect::<Vec<u8>>());
+
+    Ok(())
+}
+
+#[test]
+fn link_bandwidth_limits_throughput() -> Result<()> {
+    // 1000 bytes at 10000 bytes/second takes 100ms to transmit
+    let link = LinkConfig::new().bandwidth(10_000);
+    let (mut a, mut b) = MemorySocket::builder().link(link).pair();
+
+    let start = Instant::now();
+    for _ in 0..10 {
+        a.write_all(&[0; 100])?;
+        a.flush()?;
+    }
+
+    let mut buf = [0; 1000];
+    b.read_exact(&mut buf)?;
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    Ok(())
+}
+
+#[test]
+fn listener_link_applies_to_new_connections() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("192.51.100.2:0".parse().unwrap())?;
+    listener.set_link(Some(LinkConfig::new().latency(Duration::from_millis(50))));
+
+    let mut dialer = network.connect(listener.local_addr())?;
+    let mut socket = listener.accept()?;
+
+    let start = Instant::now();
+    socket.write_all(b"skybreaker")?;
+    socket.flush()?;
+    let mut buf = [0; 10];
+    dialer.read_exact(&mut buf)?;
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    // A link given when connecting takes precedence
+    let mut dialer = network.connect_with_link(listener.local_addr(), LinkConfig::new())?;
+    let mut socket = listener.accept()?;
+    dialer.set_nonblocking(true)?;
+    socket.write_all(b"truthwatcher")?;
+    socket.flush()?;
+    let mut buf = [0; 12];
+    dialer.read_exact(&mut buf)?;
+
+    Ok(())
+}
+
+fn partitioned_network() -> Result<(MemoryNetwork, MemoryListener, MemorySocket, MemorySocket)> {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    let dialer = network.connect_from(
+        "192.51.100.3:0".parse().unwrap(),
+        "192.51.100.2:80".parse().unwrap(),
+    )?;
+    let socket = listener.accept()?;
+
+    Ok((network, listener, dialer, socket))
+}
+
+#[test]
+fn partition_stall() -> Result<()> {
+    let (network, _listener, mut dialer, mut socket) = partitioned_network()?;
+    let partition = network.partition(
+        vec!["192.51.100.2".parse().unwrap()],
+        vec![
+            "192.51.100.3".parse().unwrap(),
+            "192.51.100.4".parse().unwrap(),
+        ],
+        PartitionMode::Stall,
+    );
+
+    assert_eq!(
+        network
+            .connect_from(
+                "192.51.100.4:0".parse().unwrap(),
+                "192.51.100.2:80".parse().unwrap(),
+            )
+            .err()
+            .map(|e| e.kind()),
+        Some(ErrorKind::TimedOut)
+    );
+
+    // Data sent across the partition is held back
+    dialer.write_all(b"sja-anat")?;
+    dialer.flush()?;
+    socket.set_read_timeout(Some(Duration::from_millis(10)))?;
+    let mut buf = [0; 8];
+    assert_eq!(
+        socket.read(&mut buf).err().map(|e| e.kind()),
+        Some(ErrorKind::WouldBlock)
+    );
+
+    network.heal(partition);
+    socket.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"sja-anat");
+    network.connect_from(
+        "192.51.100.4:0".parse().unwrap(),
+        "192.51.100.2:80".parse().unwrap(),
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn shutdown_read_during_stall() -> Result<()> {
+    let (network, _listener, mut dialer, mut socket) = partitioned_network()?;
+    network.partition(
+        vec!["192.51.100.2".parse().unwrap()],
+        vec!["192.51.100.3".parse().unwrap()],
+        PartitionMode::Stall,
+    );
+    dialer.write_all(b"held")?;
+    dialer.flush()?;
+
+    // Shutting down the read half ends the stream even though the link is stalled
+    socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+    socket.shutdown(Shutdown::Read)?;
+    let mut buf = [0; 4];
+    assert_eq!(socket.read(&mut buf)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn dropping_network_during_stall() -> Result<()> {
+    let (network, _listener, mut dialer, mut socket) = partitioned_network()?;
+    network.partition(
+        vec!["192.51.100.2".parse().unwrap()],
+        vec!["192.51.100.3".parse().unwrap()],
+        PartitionMode::Stall,
+    );
+    dialer.write_all(b"held")?;
+    dialer.flush()?;
+
+    socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+    drop(network);
+    let mut buf = [0; 4];
+    assert_eq!(socket.read(&mut buf)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn partition_reset() -> Result<()> {
+    let (network, _listener, mut dialer, mut socket) = partitioned_network()?;
+    dialer.write_all(b"lost")?;
+    dialer.flush()?;
+
+    network.partition(
+        vec!["192.51.100.3".parse().unwrap()],
+        vec!["192.51.100.2".parse().unwrap()],
+        PartitionMode::Reset,
+    );
+
+    let mut buf = [0; 4];
+    assert_eq!(
+        socket.read(&mut buf).err().map(|e| e.kind()),
+        Some(ErrorKind::ConnectionReset)
+    );
+    assert_eq!(
+        dialer.write(b"more").err().map(|e| e.kind()),
+        Some(ErrorKind::ConnectionReset)
+    );
+    assert_eq!(
+        network
+            .connect_from(
+                "192.51.100.3:0".parse().unwrap(),
+                "192.51.100.2:80".parse().unwrap(),
+            )
+            .err()
+            .map(|e| e.kind()),
+        Some(ErrorKind::ConnectionRefused)
+    );
+
+    // Addresses on the same side of the partition are unaffected
+    network.connect("192.51.100.2:80".parse().unwrap())?;
+
+    network.heal_all();
+    network.connect_from(
+        "192.51.100.3:0".parse().unwrap(),
+        "192.51.100.2:80".parse().unwrap(),
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn connect_timeout_across_stall_partition() -> Result<()> {
+    let network = std::sync::Arc::new(MemoryNetwork::new());
+    let listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    // `connect_timeout` connects from the listener's own IP, so partition it from itself
+    let ip = listener.local_addr().ip();
+    let partition = network.partition(vec![ip], vec![ip], PartitionMode::Stall);
+
+    let start = Instant::now();
+    let result = network.connect_timeout(listener.local_addr(), Duration::from_millis(100));
+    assert_eq!(result.err().map(|e| e.kind()), Some(ErrorKind::TimedOut));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    // Healing the partition while waiting lets the connection through
+    let healer = {
+        let network = network.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            network.heal(partition);
+        })
+    };
+    network.connect_timeout(listener.local_addr(), Duration::from_secs(5))?;
+    listener.accept()?;
+    healer.join().unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn partition_drops_datagrams() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let a = network.bind_datagram("192.51.100.2:0".parse().unwrap())?;
+    let b = network.bind_datagram("192.51.100.3:0".parse().unwrap())?;
+    let partition = network.partition(
+        vec!["192.51.100.2".parse().unwrap()],
+        vec!["192.51.100.3".parse().unwrap()],
+        PartitionMode::Stall,
+    );
+
+    b.set_nonblocking(true)?;
+    a.send_to(b"lost", b.local_addr())?;
+    let mut buf = [0; 4];
+    assert_eq!(
+        b.recv_from(&mut buf).err().map(|e| e.kind()),
+        Some(ErrorKind::WouldBlock)
+    );
+
+    network.heal(partition);
+    a.send_to(b"seen", b.local_addr())?;
+    assert_eq!(b.recv_from(&mut buf)?, (4, a.local_addr()));
+
+    Ok(())
+}
+
+#[test]
+fn abort_resets_peer() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+    a.write_all(b"discarded")?;
+    a.flush()?;
+    a.abort();
+
+    // Unlike a graceful close, data in flight is discarded and an error is returned
+    let mut buf = [0; 9];
+    assert_eq!(
+        b.read(&mut buf).err().map(|e| e.kind()),
+        Some(ErrorKind::ConnectionReset)
+    );
+    assert_eq!(
+        b.write(b"hello").err().map(|e| e.kind()),
+        Some(ErrorKind::ConnectionReset)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reset_connections() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    let other = network.bind("192.51.100.2:81".parse().unwrap())?;
+    let mut a = network.connect(listener.local_addr())?;
+    let mut b = network.connect(listener.local_addr())?;
+    let mut c = network.connect(other.local_addr())?;
+    let mut accepted = listener.accept()?;
+
+    assert_eq!(network.reset_connections(listener.local_addr()), 2);
+
+    let mut buf = [0; 4];
+    for socket in [&mut a, &mut b, &mut accepted].iter_mut() {
+        assert_eq!(
+            socket.read(&mut buf).err().map(|e| e.kind()),
+            Some(ErrorKind::ConnectionReset)
+        );
+    }
+
+    // Connections to other addresses are unaffected
+    c.write_all(b"fine")?;
+    c.flush()?;
+    other.accept()?.read_exact(&mut buf)?;
+
+    Ok(())
+}
+
+#[test]
+fn reset_connections_to_wildcard_listener() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("0.0.0.0:80".parse().unwrap())?;
+    let mut a = network.connect("192.51.100.2:80".parse().unwrap())?;
+    let mut b = network.connect("192.51.100.3:80".parse().unwrap())?;
+
+    assert_eq!(network.reset_connections(listener.local_addr()), 2);
+
+    let mut buf = [0; 4];
+    for socket in [&mut a, &mut b].iter_mut() {
+        assert_eq!(
+            socket.read(&mut buf).err().map(|e| e.kind()),
+            Some(ErrorKind::ConnectionReset)
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn backlog_refuses_connections_when_full() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let listener = ListenerBuilder::new()
+        .backlog(2)
+        .bind_in(&network, "192.51.100.2:80".parse().unwrap())?;
+
+    let _a = network.connect(listener.local_addr())?;
+    let _b = network.connect(listener.local_addr())?;
+    assert_eq!(
+        network
+            .connect(listener.local_addr())
+            .err()
+            .map(|e| e.kind()),
+        Some(ErrorKind::ConnectionRefused)
+    );
+
+    // Accepting a connection makes room for another
+    listener.accept()?;
+    network.connect(listener.local_addr())?;
+
+    Ok(())
+}
+
+#[test]
+fn connect_timeout_waits_for_backlog() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let listener = MemoryListener::builder()
+        .backlog(1)
+        .bind_in(&network, "192.51.100.2:80".parse().unwrap())?;
+    let address = listener.local_addr();
+    let _a = network.connect(address)?;
+
+    assert_eq!(
+        network
+            .connect_timeout(address, Duration::from_millis(10))
+            .err()
+            .map(|e| e.kind()),
+        Some(ErrorKind::TimedOut)
+    );
+    assert_eq!(
+        network
+            .connect_timeout(address, Duration::from_secs(0))
+            .err()
+            .map(|e| e.kind()),
+        Some(ErrorKind::InvalidInput)
+    );
+
+    let acceptor = std::thread::spawn(move || -> Result<MemoryListener> {
+        std::thread::sleep(Duration::from_millis(10));
+        listener.accept()?;
+        Ok(listener)
+    });
+    let mut socket = network.connect_timeout(address, Duration::from_secs(10))?;
+    let listener = acceptor.join().unwrap()?;
+
+    socket.write_all(b"urithiru")?;
+    socket.flush()?;
+    let mut buf = [0; 8];
+    listener.accept()?.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"urithiru");
+
+    Ok(())
+}
+
+#[test]
+fn connect_error_kinds() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let connect = |address: &str| {
+        network
+            .connect(address.parse().unwrap())
+            .err()
+            .map(|e| e.kind())
+    };
+
+    assert_eq!(
+        connect("192.51.100.2:80"),
+        Some(ErrorKind::ConnectionRefused)
+    );
+    assert_eq!(connect("0.0.0.0:80"), Some(ErrorKind::AddrNotAvailable));
+    assert_eq!(connect("192.51.100.2:0"), Some(ErrorKind::AddrNotAvailable));
+
+    let listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    drop(listener);
+    assert_eq!(
+        connect("192.51.100.2:80"),
+        Some(ErrorKind::ConnectionRefused)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn close_wakes_blocked_accept() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    let handle = listener.close_handle();
+
+    let acceptor = std::thread::spawn(move || listener.incoming().count());
+    std::thread::sleep(Duration::from_millis(10));
+    handle.close();
+    assert_eq!(acceptor.join().unwrap(), 0);
+
+    // The address is released once the listener is closed
+    network.bind("192.51.100.2:80".parse().unwrap())?;
+
+    Ok(())
+}
+
+#[test]
+fn close_resets_queued_connections() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    let mut queued = network.connect(listener.local_addr())?;
+
+    listener.close();
+
+    let mut buf = [0; 4];
+    assert_eq!(
+        queued.read(&mut buf).err().map(|e| e.kind()),
+        Some(ErrorKind::ConnectionReset)
+    );
+    assert_eq!(
+        listener.accept().err().map(|e| e.kind()),
+        Some(ErrorKind::ConnectionAborted)
+    );
+    assert_eq!(
+        network
+            .connect(listener.local_addr())
+            .err()
+            .map(|e| e.kind()),
+        Some(ErrorKind::ConnectionRefused)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn closed_listener_does_not_unbind_new_listener() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let address = "192.51.100.2:80".parse().unwrap();
+    let old = network.bind(address)?;
+    old.close();
+
+    let new = network.bind(address)?;
+    drop(old);
+
+    let _socket = network.connect(address)?;
+    new.accept()?;
+
+    Ok(())
+}
+
+#[test]
+fn wildcard_listener_routing() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let dual_stack = network.bind("[::]:80".parse().unwrap())?;
+    let wildcard = network.bind("0.0.0.0:80".parse().unwrap())?;
+    let exact = network.bind("192.51.100.2:80".parse().unwrap())?;
+
+    let _a = network.connect("192.51.100.2:80".parse().unwrap())?;
+    let _b = network.connect("192.51.100.3:80".parse().unwrap())?;
+    let _c = network.connect("[2001:db8::1]:80".parse().unwrap())?;
+
+    let a = exact.try_accept()?;
+    assert_eq!(a.local_addr()?, "192.51.100.2:80".parse().unwrap());
+    let b = wildcard.try_accept()?;
+    assert_eq!(b.local_addr()?, "192.51.100.3:80".parse().unwrap());
+    dual_stack.try_accept()?;
+
+    // Without an IPv4 wildcard the dual-stack listener accepts IPv4 connections too
+    drop(wildcard);
+    let _d = network.connect("192.51.100.4:80".parse().unwrap())?;
+    dual_stack.try_accept()?;
+
+    // Other ports aren't affected
+    assert_eq!(
+        network
+            .connect("192.51.100.4:81".parse().unwrap())
+            .err()
+            .map(|e| e.kind()),
+        Some(ErrorKind::ConnectionRefused)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn connect_by_host_name() -> Result<()> {
+    let network = MemoryNetwork::new();
+    network.register_host("db.internal", "192.51.100.5".parse().unwrap());
+    let listener = network.bind_to("db.internal:5432")?;
+    assert_eq!(listener.local_addr(), "192.51.100.5:5432".parse().unwrap());
+
+    let socket = network.connect_to("DB.Internal:5432")?;
+    assert_eq!(socket.peer_addr()?, "192.51.100.5:5432".parse().unwrap());
+    listener.try_accept()?;
+
+    // Literal addresses and (host, port) pairs resolve too
+    network.connect_to("192.51.100.5:5432")?;
+    network.connect_to(("db.internal", 5432))?;
+
+    Ok(())
+}
+
+#[test]
+fn connect_tries_each_record() -> Result<()> {
+    let network = MemoryNetwork::new();
+    network.register_host("api.internal", "2001:db8::6".parse().unwrap());
+    network.register_host("api.internal", "192.51.100.6".parse().unwrap());
+    let listener = network.bind("192.51.100.6:443".parse().unwrap())?;
+
+    let socket = network.connect_to("api.internal:443")?;
+    assert_eq!(socket.peer_addr()?, listener.local_addr());
+
+    // The error from the last address is returned if none succeed
+    let result = network.connect_to("api.internal:80");
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(ErrorKind::ConnectionRefused)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn resolution_failures() {
+    let network = MemoryNetwork::new();
+    let kind = |result: Result<MemorySocket>| result.err().map(|e| e.kind());
+
+    assert_eq!(
+        kind(network.connect_to("missing.internal:80")),
+        Some(ErrorKind::NotFound)
+    );
+    assert_eq!(
+        kind(network.connect_to("missing.internal")),
+        Some(ErrorKind::InvalidInput)
+    );
+    assert_eq!(
+        kind(network.connect_to("missing.internal:http")),
+        Some(ErrorKind::InvalidInput)
+    );
+
+    network.register_host("flaky.internal", "192.51.100.7".parse().unwrap());
+    network.fail_host("flaky.internal", ErrorKind::TimedOut);
+    assert_eq!(
+        kind(network.connect_to("flaky.internal:80")),
+        Some(ErrorKind::TimedOut)
+    );
+
+    network.deregister_host("flaky.internal");
+    assert_eq!(
+        kind(network.connect_to("flaky.internal:80")),
+        Some(ErrorKind::NotFound)
+    );
+}
+
+#[test]
+fn socket_stats() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::pair_with_capacity(4);
+
+    // The capacity splits the data into several chunks, blocking until each has been read
+    let reader = std::thread::spawn(move || -> Result<_> {
+        let mut buf = [0; 11];
+        b.read_exact(&mut buf)?;
+        Ok(b)
+    });
+    a.write_all(b"hello")?;
+    a.write_all(b" world")?;
+    a.flush()?;
+    let b = reader.join().unwrap()?;
+    assert_eq!(a.stats().bytes_written(), 11);
+
+    let stats = a.stats();
+    assert!(stats.flushes() >= 3);
+    assert_eq!(stats.max_queued(), 4);
+    assert_eq!(stats.bytes_read(), 0);
+
+    // Each read returns at most a single chunk
+    let stats = b.stats();
+    assert_eq!(stats.bytes_read(), 11);
+    assert_eq!(stats.reads(), a.stats().flushes());
+    assert_eq!(stats.bytes_written(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn listener_stats() -> Result<()> {
+    let network = MemoryNetwork::new();
+    let listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+
+    for _ in 0..2 {
+        let mut socket = network.connect(listener.local_addr())?;
+        let mut accepted = listener.accept()?;
+        socket.write_all(b"ping")?;
+        socket.flush()?;
+        let mut buf = [0; 4];
+        accepted.read_exact(&mut buf)?;
+        accepted.write_all(b"pong!")?;
+    }
+
+    // Connections which haven't been accepted don't count
+    let _pending = network.connect(listener.local_addr())?;
+
+    let stats = listener.stats();
+    assert_eq!(stats.accepted(), 2);
+    assert_eq!(stats.bytes_read(), 8);
+    assert_eq!(stats.bytes_written(), 10);
+
+    Ok(())
+}
+
+#[test]
+fn simulation_assigns_ports_from_seed() -> Result<()> {
+    let assign = |seed| -> Result<(SocketAddr, SocketAddr)> {
+        let network = MemoryNetwork::with_seed(seed);
+        let listener = network.bind("192.51.100.2:0".parse().unwrap())?;
+        let socket = network.connect(listener.local_addr())?;
+        Ok((listener.local_addr(), socket.local_addr()?))
+    };
+
+    assert_eq!(assign(7)?, assign(7)?);
+    assert_ne!(assign(7)?, assign(8)?);
+    assert_eq!(MemoryNetwork::with_seed(7).seed(), Some(7));
+    assert_eq!(MemoryNetwork::new().seed(), None);
+
+    Ok(())
+}
+
+/// Write each of `messages` with a separate flush, returning the sizes of the reads the peer
+/// sees.
+fn read_sizes(builder: PairBuilder, messages: &[&[u8]]) -> Result<Vec<usize>> {
+    let (mut a, mut b) = builder.pair();
+    for message in messages {
+        a.write_all(message)?;
+        a.flush()?;
+    }
+    drop(a);
+
+    let mut sizes = Vec::new();
+    let mut received = Vec::new();
+    let mut buf = [0; 64];
+    loop {
+        match b.read(&mut buf)? {
+            0 => break,
+            n => {
+                sizes.push(n);
+                received.extend_from_slice(&buf[..n]);
+            }
+        }
+    }
+    assert_eq!(received, messages.concat());
+    Ok(sizes)
+}
+
+#[test]
+fn segmentation_mtu() -> Result<()> {
+    let builder = MemorySocket::builder().segmentation(SegmentConfig::new().mtu(3));
+    assert_eq!(
+        read_sizes(builder, &[b"abcdefghij", b"kl"])?,
+        vec![3, 3, 3, 1, 2]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn segmentation_random_is_reproducible() -> Result<()> {
+    let messages: &[&[u8]] = &[b"the quick brown fox", b"jumps over", b"the lazy dog"];
+    let builder = |seed| {
+        let segmentation = SegmentConfig::new().mtu(8).random(true).coalesce(true);
+        MemorySocket::builder()
+            .segmentation(segmentation)
+            .seed(seed)
+    };
+
+    let sizes = read_sizes(builder(3), messages)?;
+    assert!(sizes.iter().all(|&size| size <= 8));
+    assert_eq!(read_sizes(builder(3), messages)?, sizes);
+
+    Ok(())
+}
+
+#[test]
+fn segmentation_coalesces_flushes() -> Result<()> {
+    let messages: &[&[u8]] = &[b"a", b"b", b"c", b"d", b"e", b"f", b"g", b"h"];
+    // Without segmentation every read returns a single flush
+    assert_eq!(
+        read_sizes(MemorySocket::builder(), messages)?,
+        vec![1; messages.len()]
+    );
+
+    let segmentation = SegmentConfig::new().coalesce(true);
+    let coalesced = (0..16)
+        .map(|seed| {
+            let builder = MemorySocket::builder()
+                .segmentation(segmentation.clone())
+                .seed(seed);
+            read_sizes(builder, messages)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    assert!(coalesced.iter().any(|sizes| sizes.len() < messages.len()));
+
+    Ok(())
+}
+
+#[test]
+fn network_segmentation() -> Result<()> {
+    let network = MemoryNetwork::with_seed(11);
+    network.set_segmentation(Some(SegmentConfig::new().mtu(2)));
+    let listener = network.bind("192.51.100.2:80".parse().unwrap())?;
+    let mut socket = network.connect(listener.local_addr())?;
+    let mut accepted = listener.accept()?;
+
+    socket.write_all(b"hello")?;
+    socket.flush()?;
+    let mut buf = [0; 5];
+    assert_eq!(accepted.read(&mut buf)?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn default_flush_policy_delivers_without_flush() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+    assert_eq!(a.flush_policy(), FlushPolicy::default());
+
+    // Nothing is in flight, so the first write is sent right away
+    a.write_all(b"a")?;
+    // While it is unread, later writes are coalesced
+    a.write_all(b"b")?;
+    a.write_all(b"c")?;
+
+    let mut buf = [0; 3];
+    assert_eq!(b.read(&mut buf)?, 1);
+    assert_eq!(b.read(&mut buf)?, 2);
+    assert_eq!(&buf[..2], b"bc");
+    assert_eq!(a.stats().flushes(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn nagle_delay_with_latency() -> Result<()> {
+    let latency = Duration::from_millis(100);
+    let delay = Duration::from_millis(10);
+    let (mut a, mut b) = MemorySocket::builder()
+        .link(LinkConfig::new().latency(latency))
+        .flush_policy(FlushPolicy::Nagle { delay })
+        .pair();
+
+    let start = Instant::now();
+    a.write_all(b"a")?;
+    a.write_all(b"b")?;
+
+    // The second write is held back for the delay rather than until the first is read
+    let mut buf = [0; 1];
+    b.read_exact(&mut buf)?;
+    b.read_exact(&mut buf)?;
+    let elapsed = start.elapsed();
+    assert!(elapsed >= latency + delay);
+    assert!(elapsed < latency * 2);
+
+    Ok(())
+}
+
+#[test]
+fn manual_flush_policy() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::builder()
+        .flush_policy(FlushPolicy::Manual)
+        .pair();
+    b.set_nonblocking(true)?;
+
+    a.write_all(b"hello")?;
+    let mut buf = [0; 5];
+    assert_eq!(b.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+    a.flush()?;
+    b.read_exact(&mut buf)?;
+
+    Ok(())
+}
+
+#[test]
+fn nodelay_sends_every_write() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+    assert!(!a.nodelay()?);
+    a.set_nodelay(true)?;
+    assert!(a.nodelay()?);
+    assert_eq!(a.flush_policy(), FlushPolicy::EveryWrite);
+
+    for _ in 0..3 {
+        a.write_all(b"x")?;
+    }
+    let mut buf = [0; 3];
+    assert_eq!(b.read(&mut buf)?, 1);
+    assert_eq!(a.stats().flushes(), 3);
+
+    a.set_nodelay(false)?;
+    assert_eq!(a.flush_policy(), FlushPolicy::default());
+
+    Ok(())
+}
+
+#[test]
+fn threshold_flush_policy() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+    a.set_flush_policy(FlushPolicy::Threshold(4));
+    b.set_nonblocking(true)?;
+
+    a.write_all(b"ab")?;
+    let mut buf = [0; 4];
+    assert_eq!(b.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+    a.write_all(b"cd")?;
+    b.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"abcd");
+
+    Ok(())
+}
+
+#[test]
+fn drop_delivers_buffered_data() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::builder()
+        .capacity(4)
+        .flush_policy(FlushPolicy::Manual)
+        .pair();
+
+    // More than the capacity is delivered, without blocking the drop
+    a.write_all(b"stormlight")?;
+    drop(a);
+
+    let mut buf = Vec::new();
+    b.read_to_end(&mut buf)?;
+    assert_eq!(buf, b"stormlight");
+
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "dropped with 4 bytes of undeliverable data")]
+fn discard_policy_panic() {
+    let (mut a, b) = MemorySocket::builder()
+        .flush_policy(FlushPolicy::Manual)
+        .discard_policy(DiscardPolicy::Panic)
+        .pair();
+
+    a.write_all(b"lost").unwrap();
+    drop(b);
+    drop(a);
+}
+
+#[test]
+fn abort_does_not_report_discarded_data() -> Result<()> {
+    let (mut a, _b) = MemorySocket::builder()
+        .flush_policy(FlushPolicy::Manual)
+        .discard_policy(DiscardPolicy::Panic)
+        .pair();
+
+    a.write_all(b"lost")?;
+    a.abort();
+
+    Ok(())
+}
+
+#[test]
+fn mock_peer_script() -> Result<()> {
+    let (mut socket, mock) = MockPeer::new()
+        .expect(b"HELLO")
+        .respond(b"WELCOME")
+        .expect_with(4, |bytes| bytes.starts_with(b"ID"))
+        .respond(b"OK")
+        .close()
+        .build();
+
+    let mut buf = [0; 7];
+    socket.write_all(b"HELLO")?;
+    socket.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"WELCOME");
+
+    socket.write_all(b"ID42")?;
+    let mut response = Vec::new();
+    socket.read_to_end(&mut response)?;
+    assert_eq!(response, b"OK");
+
+    mock.join();
+    Ok(())
+}
+
+#[test]
+fn mock_peer_error() -> Result<()> {
+    let (mut socket, mock) = MockPeer::new()
+        .respond(b"partial")
+        .error(ErrorKind::ConnectionReset)
+        .build();
+
+    let mut buf = [0; 7];
+    socket.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"partial");
+    let error = socket.read(&mut buf).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::ConnectionReset);
+
+    mock.join();
+    Ok(())
+}
+
+#[test]
+fn mock_peer_wait() -> Result<()> {
+    let (mut socket, mock) = MockPeer::new()
+        .wait(Duration::from_millis(50))
+        .respond(b"late")
+        .build();
+
+    let start = Instant::now();
+    let mut buf = [0; 4];
+    socket.read_exact(&mut buf)?;
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    mock.join();
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "first difference at byte 1")]
+fn mock_peer_mismatch() {
+    let (mut socket, mock) = MockPeer::new().expect(b"ping").build();
+
+    socket.write_all(b"pong").unwrap();
+    socket.flush().unwrap();
+    mock.join();
+}
+
+#[test]
+#[should_panic(expected = "unexpected data after the end of the script")]
+fn mock_peer_unexpected_data() {
+    let (mut socket, mock) = MockPeer::new()
+        .expect(b"ping")
+        .wait(Duration::from_millis(50))
+        .build();
+
+    socket.write_all(b"ping").unwrap();
+    socket.write_all(b"extra").unwrap();
+    socket.flush().unwrap();
+    mock.join();
+}
+
+#[test]
+#[should_panic(expected = "timed out waiting for 4 bytes")]
+fn mock_peer_timeout() {
+    let (_socket, mock) = MockPeer::new()
+        .timeout(Duration::from_millis(10))
+        .expect(b"ping")
+        .build();
+
+    mock.join();
+}
+
+/// A tiny line-based client: sends a greeting and each command, collecting the responses.
+fn run_client<S: Read + Write>(mut socket: S, commands: &[&[u8]]) -> Result<Vec<u8>> {
+    socket.write_all(b"HELLO\n")?;
+    for command in commands {
+        socket.write_all(command)?;
+        socket.flush()?;
+    }
+    let mut responses = Vec::new();
+    socket.read_to_end(&mut responses)?;
+    Ok(responses)
+}
+
+#[test]
+fn record_and_replay_session() -> Result<()> {
+    let (client, mut server) = MemorySocket::new_pair();
+    let server = std::thread::spawn(move || -> Result<()> {
+        let mut buf = [0; 11];
+        server.read_exact(&mut buf)?;
+        server.write_all(b"OK \x00\xff\n")?;
+        server.flush()?;
+        server.shutdown(Shutdown::Write)
+    });
+
+    let mut recorder = Recorder::new(client);
+    let responses = run_client(&mut recorder, &[b"PING\n"])?;
+    assert_eq!(responses, b"OK \x00\xff\n");
+    server.join().unwrap()?;
+
+    let transcript = recorder.transcript().clone();
+    assert!(matches!(
+        transcript.events().last(),
+        Some(Event::Eof { .. })
+    ));
+
+    // The transcript survives a round trip through the text format
+    let mut text = Vec::new();
+    transcript.write_to(&mut text)?;
+    let loaded = Transcript::read_from(&text[..])?;
+    assert_eq!(loaded, transcript);
+
+    // The same client passes against the replayed server
+    let (socket, mock) = loaded.replay().build();
+    assert_eq!(run_client(socket, &[b"PING\n"])?, b"OK \x00\xff\n");
+    mock.join();
+
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "mock peer received unexpected data")]
+fn replay_detects_regression() {
+    let transcript = Transcript::read_from(
+        &b"memory-socket transcript v1\n\
+           > 0.000100 b\"HELLO\\nPING\\n\"\n\
+           < 0.000200 b\"PONG\\n\"\n\
+           < 0.000300 EOF\n"[..],
+    )
+    .unwrap();
+
+    let (socket, mock) = transcript.replay().timeout(Duration::from_secs(1)).build();
+    let _ = run_client(socket, &[b"PONG\n"]);
+    mock.join();
+}
+
+#[test]
+fn replay_with_timing() -> Result<()> {
+    let mut transcript = Transcript::new();
+    transcript.push(Event::Received {
+        offset: Duration::from_millis(50),
+        data: b"late".to_vec(),
+    });
+
+    let (mut socket, mock) = transcript.replay_with_timing().build();
+    let start = Instant::now();
+    let mut buf = [0; 4];
+    socket.read_exact(&mut buf)?;
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    mock.join();
+    Ok(())
+}
+
+#[test]
+fn invalid_transcript() {
+    let cases: &[&[u8]] = &[
+        b"> 0.1 b\"ping\"\n",
+        b"memory-socket transcript v1\n= 0.1 b\"ping\"\n",
+        b"memory-socket transcript v1\n> 1e3 b\"ping\"\n",
+        b"memory-socket transcript v1\n> 0.1 \"ping\"\n",
+        b"memory-socket transcript v1\n> 0.1 b\"\\q\"\n",
+    ];
+    for case in cases {
+        let error = Transcript::read_from(*case).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,185 @@
+use memory_socket::{Event, LinkConfig, MemoryListener, MemorySocket, Recorder};
+use std::{
+    io::Result,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+//
+// MemoryListener Tests
+//
+
+#[tokio::test]
+async fn simple_connect() -> Result<()> {
+    let address: SocketAddr = "192.51.100.5:10".parse().unwrap();
+    let mut listener = MemoryListener::bind(address)?;
+
+    let mut dialer = MemorySocket::connect(address)?;
+    let mut listener_socket = listener.accept_async().await?;
+
+    dialer.write_all(b"foo").await?;
+    dialer.flush().await?;
+
+    let mut buf = [0; 3];
+    listener_socket.read_exact(&mut buf).await?;
+    assert_eq!(&buf, b"foo");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn incoming_stream() -> Result<()> {
+    use futures_core::Stream;
+    use std::{future::poll_fn, pin::Pin};
+
+    let address: SocketAddr = "192.51.100.5:11".parse().unwrap();
+    let mut listener = MemoryListener::bind(address)?;
+    let mut incoming = listener.incoming_stream();
+
+    let _dialer = MemorySocket::connect(address)?;
+    let socket = poll_fn(|context| Pin::new(&mut incoming).poll_next(context))
+        .await
+        .unwrap()?;
+    assert_eq!(socket.local_addr()?, address);
+
+    Ok(())
+}
+
+//
+// MemorySocket Tests
+//
+
+#[tokio::test]
+async fn simple_write_read() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+
+    a.write_all(b"hello world").await?;
+    a.flush().await?;
+    drop(a);
+
+    let mut v = Vec::new();
+    b.read_to_end(&mut v).await?;
+    assert_eq!(v, b"hello world");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn partial_read() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+
+    a.write_all(b"foobar").await?;
+    a.flush().await?;
+
+    let mut buf = [0; 3];
+    b.read_exact(&mut buf).await?;
+    assert_eq!(&buf, b"foo");
+    b.read_exact(&mut buf).await?;
+    assert_eq!(&buf, b"bar");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_half_closes_socket() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::new_pair();
+
+    a.write_all(b"way of kings").await?;
+    AsyncWriteExt::shutdown(&mut a).await?;
+
+    let mut v = Vec::new();
+    b.read_to_end(&mut v).await?;
+    assert_eq!(v, b"way of kings");
+
+    b.write_all(b"rhythm of war").await?;
+    b.flush().await?;
+
+    let mut buf = [0; 13];
+    a.read_exact(&mut buf).await?;
+    assert_eq!(&buf, b"rhythm of war");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn flush_waits_for_slow_reader() -> Result<()> {
+    let (mut a, mut b) = MemorySocket::pair_with_capacity(4);
+
+    let reader = tokio::spawn(async move {
+        let mut buf = [0; 17];
+        b.read_exact(&mut buf).await.map(|_| buf)
+    });
+
+    a.write_all(b"words of radiance").await?;
+    a.flush().await?;
+    assert_eq!(&reader.await.unwrap()?, b"words of radiance");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn into_split_across_tasks() -> Result<()> {
+    let (a, mut b) = MemorySocket::new_pair();
+    let (mut read_half, mut write_half) = a.into_split();
+
+    let writer = tokio::spawn(async move {
+        write_half.write_all(b"lightweaver").await?;
+        write_half.shutdown().await
+    });
+
+    let mut v = Vec::new();
+    b.read_to_end(&mut v).await?;
+    assert_eq!(v, b"lightweaver");
+    writer.await.unwrap()?;
+
+    b.write_all(b"elsecaller").await?;
+    b.flush().await?;
+    let mut buf = [0; 10];
+    read_half.read_exact(&mut buf).await?;
+    assert_eq!(&buf, b"elsecaller");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn link_latency_delays_delivery() -> Result<()> {
+    let link = LinkConfig::new().latency(Duration::from_millis(50));
+    let (mut a, mut b) = MemorySocket::builder().link(link).pair();
+
+    let start = Instant::now();
+    a.write_all(b"stoneward").await?;
+    a.flush().await?;
+
+    let mut buf = [0; 9];
+    b.read_exact(&mut buf).await?;
+    assert_eq!(&buf, b"stoneward");
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn recorder() -> Result<()> {
+    let (socket, mut peer) = MemorySocket::new_pair();
+    let mut recorder = Recorder::new(socket);
+
+    recorder.write_all(b"ping").await?;
+    recorder.flush().await?;
+    let mut buf = [0; 4];
+    peer.read_exact(&mut buf).await?;
+    peer.write_all(b"pong").await?;
+    drop(peer);
+
+    let mut response = Vec::new();
+    recorder.read_to_end(&mut response).await?;
+    assert_eq!(response, b"pong");
+
+    let events = recorder.transcript().events();
+    assert!(matches!(&events[0], Event::Sent { data, .. } if data == b"ping"));
+    assert!(matches!(&events[1], Event::Received { data, .. } if data == b"pong"));
+    assert!(matches!(events[2], Event::Eof { .. }));
+    assert_eq!(events.len(), 3);
+
+    Ok(())
+}